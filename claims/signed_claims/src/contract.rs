@@ -4,7 +4,7 @@ use crate::state::{
     TOTAL_REGISTERED,
 };
 use cosmwasm_std::{
-    coin, coins, to_binary, BankMsg, Binary, Deps, DepsMut, Env, Event, MessageInfo, Response,
+    coin, coins, to_json_binary, BankMsg, Binary, Deps, DepsMut, Env, Event, MessageInfo, Response,
     StdResult, Uint128,
 };
 use eyre::{ensure, Result};
@@ -106,7 +106,7 @@ pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> R
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
-    to_binary(&match msg {
+    to_json_binary(&match msg {
         QueryMsg::Claim { address } => coin(
             REWARDS.may_load(deps.storage, address)?.unwrap_or(0),
             DENOM.load(deps.storage)?,
@@ -0,0 +1,39 @@
+//! Builds the Merkle tree of a campaign from a CSV of `address,amount` lines, and prints the
+//! `MerkleRoot` to instantiate with along with the proof of every claim as JSON.
+//!
+//! cargo run --example merkle_tree -- claims.csv
+
+use eyre::{Context, Result};
+use serde_json::json;
+use simple_claims::merkle::MerkleTree;
+use simple_claims::msg::MerkleRoot;
+
+fn main() -> Result<()> {
+    let path = std::env::args()
+        .nth(1)
+        .ok_or_else(|| eyre::eyre!("usage: merkle_tree <claims.csv>"))?;
+    let csv = std::fs::read_to_string(&path).wrap_err_with(|| format!("reading {path}"))?;
+    let tree = MerkleTree::from_csv(&csv)?;
+    let claims: Vec<_> = tree
+        .claims()
+        .iter()
+        .enumerate()
+        .map(|(index, (address, amount))| {
+            json!({
+                "address": address,
+                "index": index,
+                "amount": amount,
+                "proof": tree.proof(index).iter().map(hex::encode).collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+    let root = MerkleRoot {
+        root: hex::encode(tree.root()),
+        total: tree.total(),
+    };
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&json!({ "merkle": root, "claims": claims }))?
+    );
+    Ok(())
+}
@@ -1,15 +1,20 @@
-use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg};
-use crate::state::{ADMIN, BANK, DENOM, REGISTER};
+use crate::merkle::{self, parse_hash};
+use crate::msg::{ExecuteMsg, InstantiateMsg, MerkleRoot, MigrateMsg, QueryMsg};
+use crate::state::{ADMIN, BANK, CLAIMED_LEAVES, CLEARED, DENOM, MERKLE_ROOT, REGISTER};
 use cosmwasm_std::{
-    coin, to_binary, BankMsg, Binary, Coin, Deps, DepsMut, Env, MessageInfo, Response, StdResult,
-    Uint128,
+    coin, to_json_binary, BankMsg, Binary, Coin, Deps, DepsMut, Env, MessageInfo, Response,
+    StdResult, Uint128,
 };
-use eyre::{ensure, Result};
+use cw_storage_plus::Bound;
+use eyre::{ensure, eyre, Result};
 
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::Order::Ascending;
 
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn migrate(_deps: DepsMut, _env: Env, _msg: MigrateMsg) -> StdResult<Response> {
     Ok(Response::new())
@@ -32,6 +37,14 @@ pub fn instantiate(
         REGISTER.save(deps.storage, addr, &amt)?;
         total += amt;
     }
+    if let Some(MerkleRoot {
+        root,
+        total: tree_total,
+    }) = msg.merkle
+    {
+        MERKLE_ROOT.save(deps.storage, &hex::encode(parse_hash(&root)?))?;
+        total += tree_total;
+    }
     ensure!(
         total == amount,
         "Provided funds must be exactly what is distributed."
@@ -45,6 +58,7 @@ pub fn instantiate(
 pub fn execute(deps: DepsMut, _env: Env, info: MessageInfo, msg: ExecuteMsg) -> Result<Response> {
     match msg {
         ExecuteMsg::Claim {} => {
+            ensure_not_cleared(deps.as_ref())?;
             let amount = REGISTER.load(deps.storage, info.sender.clone())?;
             REGISTER.remove(deps.storage, info.sender.clone());
             let bank = BANK.load(deps.storage)?;
@@ -54,26 +68,114 @@ pub fn execute(deps: DepsMut, _env: Env, info: MessageInfo, msg: ExecuteMsg) ->
                 amount: vec![coin(amount.u128(), DENOM.load(deps.storage)?)],
             }))
         }
+        ExecuteMsg::ClaimWithProof {
+            index,
+            amount,
+            proof,
+        } => {
+            ensure_not_cleared(deps.as_ref())?;
+            let root = MERKLE_ROOT
+                .may_load(deps.storage)?
+                .ok_or_else(|| eyre!("there is no merkle tree to claim from"))?;
+            let proof = proof
+                .iter()
+                .map(|hash| parse_hash(hash))
+                .collect::<Result<Vec<_>>>()?;
+            ensure!(
+                merkle::verify(
+                    &parse_hash(&root)?,
+                    index,
+                    info.sender.as_str(),
+                    amount,
+                    &proof
+                ),
+                "invalid merkle proof"
+            );
+            let (word_index, bit) = leaf_position(index);
+            let word = CLAIMED_LEAVES
+                .may_load(deps.storage, word_index)?
+                .unwrap_or_default();
+            ensure!(word & bit == 0, "already claimed");
+            CLAIMED_LEAVES.save(deps.storage, word_index, &(word | bit))?;
+            let bank = BANK.load(deps.storage)?;
+            ensure!(amount <= bank, "claims exceed the funds left");
+            BANK.save(deps.storage, &(bank - amount))?;
+            Ok(Response::new().add_message(BankMsg::Send {
+                to_address: info.sender.into(),
+                amount: vec![coin(amount.u128(), DENOM.load(deps.storage)?)],
+            }))
+        }
         ExecuteMsg::Clear {} => {
             let admin = ADMIN.load(deps.storage)?;
             ensure!(info.sender == admin, "only admin can add claims");
+            ensure_not_cleared(deps.as_ref())?;
             let mut res = Response::new();
             for entry in REGISTER.range(deps.storage, None, None, Ascending) {
                 let (addr, amt) = entry?;
                 res = res.add_attribute(&addr, amt);
             }
+            let bank = BANK.load(deps.storage)?;
+            BANK.save(deps.storage, &Uint128::zero())?;
+            CLEARED.save(deps.storage, &true)?;
             Ok(res.add_message(BankMsg::Send {
                 to_address: admin.into(),
-                amount: vec![coin(
-                    BANK.load(deps.storage)?.u128(),
-                    DENOM.load(deps.storage)?,
-                )],
+                amount: vec![coin(bank.u128(), DENOM.load(deps.storage)?)],
             }))
         }
     }
 }
 
+/// The word of `CLAIMED_LEAVES` holding leaf `index`, and the bit of the leaf in it.
+fn leaf_position(index: u64) -> (u64, u64) {
+    (index / 64, 1 << (index % 64))
+}
+
+fn ensure_not_cleared(deps: Deps) -> Result<()> {
+    ensure!(
+        !CLEARED.may_load(deps.storage)?.unwrap_or_default(),
+        "the campaign was cleared"
+    );
+    Ok(())
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(_deps: Deps, _env: Env, _msg: QueryMsg) -> StdResult<Binary> {
-    to_binary(&())
+pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::Claimable { address } => to_json_binary(&coin(
+            REGISTER
+                .may_load(deps.storage, address)?
+                .unwrap_or_default()
+                .u128(),
+            DENOM.load(deps.storage)?,
+        )),
+        QueryMsg::MerkleClaimed { index } => {
+            let (word_index, bit) = leaf_position(index);
+            let word = CLAIMED_LEAVES
+                .may_load(deps.storage, word_index)?
+                .unwrap_or_default();
+            to_json_binary(&(word & bit != 0))
+        }
+        QueryMsg::Unclaimed { start_after, limit } => {
+            let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+            let unclaimed = REGISTER
+                .range(
+                    deps.storage,
+                    start_after.map(Bound::exclusive),
+                    None,
+                    Ascending,
+                )
+                .take(limit)
+                .collect::<StdResult<Vec<_>>>()?;
+            to_json_binary(&unclaimed)
+        }
+        QueryMsg::Bank {} => to_json_binary(&coin(
+            BANK.load(deps.storage)?.u128(),
+            DENOM.load(deps.storage)?,
+        )),
+        QueryMsg::Denom {} => to_json_binary(&DENOM.load(deps.storage)?),
+        QueryMsg::Admin {} => to_json_binary(&ADMIN.load(deps.storage)?),
+        QueryMsg::Cleared {} => {
+            to_json_binary(&CLEARED.may_load(deps.storage)?.unwrap_or_default())
+        }
+    }
 }
@@ -1,6 +1,7 @@
 #![allow(clippy::derive_partial_eq_without_eq)]
 
 pub mod contract;
+pub mod merkle;
 pub mod msg;
 mod state;
 
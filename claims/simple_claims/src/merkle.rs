@@ -0,0 +1,126 @@
+//! Merkle trees of claims, for campaigns with too many recipients to list at instantiation.
+//!
+//! A leaf is the sha256 of a 0x00 byte, the 8 byte big endian index of the claim, its 16 byte
+//! big endian amount and the claimant address. An inner node is the sha256 of a 0x01 byte and
+//! its two children, and a level with an odd number of nodes pairs its last node with itself.
+//! A proof lists the siblings on the path from a leaf to the root, and the bits of the index,
+//! from the lowest, tell whether each sibling is on the right (0) or on the left (1).
+//!
+//! `MerkleTree` builds trees and proofs offline, e.g. from a CSV of `address,amount` lines.
+
+use cosmwasm_std::{Addr, Uint128};
+use eyre::{bail, ensure, eyre, Result};
+use sha2::{Digest, Sha256};
+
+pub type Hash = [u8; 32];
+
+pub fn leaf_hash(index: u64, address: &str, amount: Uint128) -> Hash {
+    Sha256::new()
+        .chain_update([0])
+        .chain_update(index.to_be_bytes())
+        .chain_update(amount.u128().to_be_bytes())
+        .chain_update(address)
+        .finalize()
+        .into()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    Sha256::new()
+        .chain_update([1])
+        .chain_update(left)
+        .chain_update(right)
+        .finalize()
+        .into()
+}
+
+/// Whether `proof` shows that the claim of `amount` by `address` is leaf `index` under `root`.
+pub fn verify(root: &Hash, index: u64, address: &str, amount: Uint128, proof: &[Hash]) -> bool {
+    let mut hash = leaf_hash(index, address, amount);
+    let mut position = index;
+    for sibling in proof {
+        hash = match position & 1 {
+            0 => node_hash(&hash, sibling),
+            _ => node_hash(sibling, &hash),
+        };
+        position >>= 1;
+    }
+    position == 0 && hash == *root
+}
+
+/// Parses a hex encoded hash.
+pub fn parse_hash(hex_hash: &str) -> Result<Hash> {
+    hex::decode(hex_hash)?
+        .try_into()
+        .map_err(|_| eyre!("hashes must be 32 bytes: {hex_hash}"))
+}
+
+pub struct MerkleTree {
+    claims: Vec<(Addr, Uint128)>,
+    /// Every level of the tree, from the leaves to the root.
+    levels: Vec<Vec<Hash>>,
+}
+
+impl MerkleTree {
+    pub fn new(claims: Vec<(Addr, Uint128)>) -> Result<Self> {
+        ensure!(!claims.is_empty(), "a tree needs at least one claim");
+        let mut levels = vec![claims
+            .iter()
+            .enumerate()
+            .map(|(index, (address, amount))| leaf_hash(index as u64, address.as_str(), *amount))
+            .collect::<Vec<_>>()];
+        while levels[levels.len() - 1].len() > 1 {
+            let level = levels[levels.len() - 1]
+                .chunks(2)
+                .map(|pair| node_hash(&pair[0], pair.last().unwrap()))
+                .collect();
+            levels.push(level);
+        }
+        Ok(MerkleTree { claims, levels })
+    }
+
+    /// Builds a tree from `address,amount` lines, in order. A header line and blank lines are
+    /// skipped.
+    pub fn from_csv(csv: &str) -> Result<Self> {
+        let mut claims = vec![];
+        for (number, line) in csv.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || (number == 0 && line.eq_ignore_ascii_case("address,amount")) {
+                continue;
+            }
+            let Some((address, amount)) = line.split_once(',') else {
+                bail!("line {}: expected address,amount", number + 1);
+            };
+            let amount = amount
+                .trim()
+                .parse::<u128>()
+                .map_err(|e| eyre!("line {}: invalid amount: {e}", number + 1))?;
+            claims.push((Addr::unchecked(address.trim()), Uint128::new(amount)));
+        }
+        Self::new(claims)
+    }
+
+    pub fn root(&self) -> Hash {
+        self.levels[self.levels.len() - 1][0]
+    }
+
+    /// The amount to fund the campaign with.
+    pub fn total(&self) -> Uint128 {
+        self.claims.iter().map(|(_, amount)| amount).sum()
+    }
+
+    pub fn claims(&self) -> &[(Addr, Uint128)] {
+        &self.claims
+    }
+
+    /// The proof of the claim at `index`.
+    pub fn proof(&self, index: usize) -> Vec<Hash> {
+        let mut position = index;
+        let mut proof = vec![];
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = (position ^ 1).min(level.len() - 1);
+            proof.push(level[sibling]);
+            position >>= 1;
+        }
+        proof
+    }
+}
@@ -7,16 +7,56 @@ pub struct MigrateMsg {}
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {
+    #[serde(default)]
     pub claims: Vec<(Addr, Uint128)>,
+    /// Claims committed to by the root of a `merkle::MerkleTree` rather than listed, for
+    /// campaigns too large to store at instantiation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merkle: Option<MerkleRoot>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MerkleRoot {
+    /// Hex encoded root of the tree.
+    pub root: String,
+    /// Sum of the claims in the tree.
+    pub total: Uint128,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
     Claim {},
+    /// Claim `amount` as leaf `index` of the Merkle tree, with the hex encoded `proof` of it.
+    ClaimWithProof {
+        index: u64,
+        amount: Uint128,
+        proof: Vec<String>,
+    },
     Clear {},
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
-pub enum QueryMsg {}
+pub enum QueryMsg {
+    /// Returns the coin registered to `address` at instantiation and not claimed yet. Claims of
+    /// the Merkle tree are not registered, see `MerkleClaimed`.
+    Claimable {
+        address: Addr,
+    },
+    /// Returns whether leaf `index` of the Merkle tree was claimed.
+    MerkleClaimed {
+        index: u64,
+    },
+    /// Returns a page of the registered claims not claimed yet, ordered by address.
+    Unclaimed {
+        start_after: Option<Addr>,
+        limit: Option<u32>,
+    },
+    /// Returns the coin left to claim, including the unclaimed part of the Merkle total.
+    Bank {},
+    Denom {},
+    Admin {},
+    /// Returns whether the admin cleared the campaign, after which nothing can be claimed.
+    Cleared {},
+}
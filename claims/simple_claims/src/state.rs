@@ -4,5 +4,12 @@ use cw_storage_plus::{Item, Map};
 pub const ADMIN: Item<Addr> = Item::new("admin");
 pub const BANK: Item<Uint128> = Item::new("bank");
 pub const DENOM: Item<String> = Item::new("denom");
+/// Set once the admin took back the bank. Missing before the first clear.
+pub const CLEARED: Item<bool> = Item::new("cleared");
 
 pub const REGISTER: Map<Addr, Uint128> = Map::new("register");
+
+/// Hex encoded root of the Merkle tree of claims, if any.
+pub const MERKLE_ROOT: Item<String> = Item::new("merkle_root");
+/// Claimed leaves of the Merkle tree, 64 per word: leaf `i` is bit `i % 64` of word `i / 64`.
+pub const CLAIMED_LEAVES: Map<u64, u64> = Map::new("claimed_leaves");
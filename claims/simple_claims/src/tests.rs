@@ -1,9 +1,10 @@
-use crate::contract::{execute, instantiate};
+use crate::contract::{execute, instantiate, query};
+use crate::merkle::{self, MerkleTree};
 use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-use cosmwasm_std::{coins, Addr, Attribute, BankMsg, CosmosMsg, Uint128};
+use cosmwasm_std::{coin, coins, from_json, Addr, Attribute, BankMsg, Coin, CosmosMsg, Uint128};
 use eyre::Result;
 
-use crate::msg::{ExecuteMsg, InstantiateMsg};
+use crate::msg::{ExecuteMsg, InstantiateMsg, MerkleRoot, QueryMsg};
 
 #[test]
 fn full_flow() -> Result<()> {
@@ -16,6 +17,7 @@ fn full_flow() -> Result<()> {
             (Addr::unchecked("p2"), Uint128::from(5u8)),
             (Addr::unchecked("p3"), Uint128::from(6u8)),
         ],
+        merkle: None,
     };
     let info = mock_info("admin0000", &coins(15, "ucarrot"));
     let _ = instantiate(deps.as_mut(), mock_env(), info, msg)?;
@@ -55,3 +57,154 @@ fn full_flow() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn merkle_claims() -> Result<()> {
+    let tree = MerkleTree::from_csv("address,amount\np1,4\np2,5\n\np3,6\np4,7\np5,8\n")?;
+    assert_eq!(tree.total(), Uint128::from(30u8));
+    for (index, (address, amount)) in tree.claims().iter().enumerate() {
+        let proof = tree.proof(index);
+        assert!(merkle::verify(
+            &tree.root(),
+            index as u64,
+            address.as_str(),
+            *amount,
+            &proof
+        ));
+        assert!(!merkle::verify(
+            &tree.root(),
+            index as u64,
+            address.as_str(),
+            *amount + Uint128::one(),
+            &proof
+        ));
+    }
+
+    let mut deps = mock_dependencies();
+    let msg = InstantiateMsg {
+        claims: vec![(Addr::unchecked("p9"), Uint128::from(2u8))],
+        merkle: Some(MerkleRoot {
+            root: hex::encode(tree.root()),
+            total: tree.total(),
+        }),
+    };
+    let info = mock_info("admin0000", &coins(32, "ucarrot"));
+    instantiate(deps.as_mut(), mock_env(), info, msg)?;
+
+    let claim = |index: usize, amount: u8| ExecuteMsg::ClaimWithProof {
+        index: index as u64,
+        amount: Uint128::from(amount),
+        proof: tree.proof(index).iter().map(hex::encode).collect(),
+    };
+    let r = execute(deps.as_mut(), mock_env(), mock_info("p5", &[]), claim(4, 8))?;
+    assert_eq!(
+        r.messages[0].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "p5".to_string(),
+            amount: coins(8, "ucarrot"),
+        })
+    );
+    assert!(execute(deps.as_mut(), mock_env(), mock_info("p5", &[]), claim(4, 8)).is_err());
+    assert!(execute(deps.as_mut(), mock_env(), mock_info("p2", &[]), claim(1, 6)).is_err());
+    assert!(execute(deps.as_mut(), mock_env(), mock_info("p1", &[]), claim(1, 5)).is_err());
+    execute(deps.as_mut(), mock_env(), mock_info("p2", &[]), claim(1, 5))?;
+    for (index, claimed) in [(0, false), (1, true), (4, true), (65, false)] {
+        let r: bool = from_json(&query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::MerkleClaimed { index },
+        )?)?;
+        assert_eq!(r, claimed, "leaf {index}");
+    }
+    // Claimable only covers registered claims.
+    let claimable: Coin = from_json(&query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::Claimable {
+            address: Addr::unchecked("p1"),
+        },
+    )?)?;
+    assert_eq!(claimable, coin(0, "ucarrot"));
+
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("admin0000", &[]),
+        ExecuteMsg::Clear {},
+    )?;
+    assert_eq!(
+        r.messages[0].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "admin0000".to_string(),
+            amount: coins(19, "ucarrot"),
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn queries() -> Result<()> {
+    let mut deps = mock_dependencies();
+    let msg = InstantiateMsg {
+        claims: (1..=4u8)
+            .map(|i| (Addr::unchecked(format!("p{i}")), Uint128::from(i)))
+            .collect(),
+        merkle: None,
+    };
+    let info = mock_info("admin0000", &coins(10, "ucarrot"));
+    instantiate(deps.as_mut(), mock_env(), info, msg)?;
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("p2", &[]),
+        ExecuteMsg::Claim {},
+    )?;
+
+    let claimable = |deps: cosmwasm_std::Deps, address: &str| -> Result<Coin> {
+        Ok(from_json(&query(
+            deps,
+            mock_env(),
+            QueryMsg::Claimable {
+                address: Addr::unchecked(address),
+            },
+        )?)?)
+    };
+    assert_eq!(claimable(deps.as_ref(), "p3")?, coin(3, "ucarrot"));
+    assert_eq!(claimable(deps.as_ref(), "p2")?, coin(0, "ucarrot"));
+    let unclaimed: Vec<(Addr, Uint128)> = from_json(&query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::Unclaimed {
+            start_after: Some(Addr::unchecked("p1")),
+            limit: Some(1),
+        },
+    )?)?;
+    assert_eq!(unclaimed, [(Addr::unchecked("p3"), Uint128::from(3u8))]);
+    let bank: Coin = from_json(&query(deps.as_ref(), mock_env(), QueryMsg::Bank {})?)?;
+    assert_eq!(bank, coin(8, "ucarrot"));
+    let denom: String = from_json(&query(deps.as_ref(), mock_env(), QueryMsg::Denom {})?)?;
+    assert_eq!(denom, "ucarrot");
+    let admin: Addr = from_json(&query(deps.as_ref(), mock_env(), QueryMsg::Admin {})?)?;
+    assert_eq!(admin, "admin0000");
+    let cleared: bool = from_json(&query(deps.as_ref(), mock_env(), QueryMsg::Cleared {})?)?;
+    assert!(!cleared);
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("admin0000", &[]),
+        ExecuteMsg::Clear {},
+    )?;
+    let cleared: bool = from_json(&query(deps.as_ref(), mock_env(), QueryMsg::Cleared {})?)?;
+    assert!(cleared);
+    let bank: Coin = from_json(&query(deps.as_ref(), mock_env(), QueryMsg::Bank {})?)?;
+    assert_eq!(bank, coin(0, "ucarrot"));
+    assert!(execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("p3", &[]),
+        ExecuteMsg::Claim {},
+    )
+    .is_err());
+    Ok(())
+}
@@ -0,0 +1,268 @@
+//! Measures the gas compass-cw uses in `cosmwasm-vm` for valsets of different sizes.
+//!
+//! Run with `cargo run --release -- [--wasm <path>] [--out <path>]` from this directory.
+//! Without `--wasm` the contract is first built with `scripts/build_release.sh`, so that gas is
+//! measured on the artifact that is deployed. The report is a markdown table, regenerate
+//! `gas_report.md` whenever compass-cw changes so that changes in gas show up in review.
+
+use compass_cw::contract::POWER_THRESHOLD;
+use compass_cw::msg::{
+    Deadline, ExecuteMsg, ExecutePayload, InstantiateMsg, LogicCallArgs, PauseState, Thresholds,
+    Valset, ValsetId,
+};
+use compass_cw_client::{sign_payload, ConsensusBuilder, Signer};
+use cosmwasm_std::{Addr, Binary, Empty, Env, Uint256};
+use cosmwasm_vm::testing::{
+    mock_env, mock_info, mock_instance_with_options, MockApi, MockInstanceOptions, MockQuerier,
+    MockStorage,
+};
+use cosmwasm_vm::{call_execute, call_instantiate, Instance};
+use eyre::{bail, eyre, Result, WrapErr};
+use k256::ecdsa::SigningKey;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+mod probestack;
+
+const VALIDATORS: [usize; 6] = [10, 50, 100, 150, 200, 300];
+
+/// CosmWasm gas per Cosmos SDK gas, as configured by default in wasmd.
+const GAS_MULTIPLIER: u64 = 140_000_000;
+
+/// High enough for every operation, so that the report shows how far above a block gas limit
+/// an operation is rather than failing.
+const GAS_LIMIT: u64 = 1 << 60;
+
+type VmInstance = Instance<MockApi, MockStorage, MockQuerier>;
+
+struct Measurement {
+    validators: usize,
+    signers: usize,
+    operation: &'static str,
+    gas: u64,
+}
+
+fn main() -> Result<()> {
+    let mut wasm = None;
+    let mut out = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .map(PathBuf::from)
+            .ok_or_else(|| eyre!("{arg} needs a path"));
+        match arg.as_str() {
+            "--wasm" => wasm = Some(value?),
+            "--out" => out = Some(value?),
+            _ => bail!("Unknown argument {arg}"),
+        }
+    }
+    let wasm = match wasm {
+        Some(wasm) => wasm,
+        None => build_wasm()?,
+    };
+    let code = std::fs::read(&wasm).wrap_err_with(|| format!("reading {}", wasm.display()))?;
+
+    // Compass is instantiated with every threshold at POWER_THRESHOLD.
+    let thresholds = Thresholds {
+        update_valset: POWER_THRESHOLD,
+        logic_call: POWER_THRESHOLD,
+        fund_release: POWER_THRESHOLD,
+        bridge: POWER_THRESHOLD,
+        admin: POWER_THRESHOLD,
+        migrate: POWER_THRESHOLD,
+    };
+    let mut measurements = vec![];
+    for validators in VALIDATORS {
+        let validator_keys = keys(0, validators);
+        let current = valset(0, &validator_keys);
+        let power = current.powers[0] as u64;
+        let minimal = POWER_THRESHOLD.div_ceil(power) as usize;
+        for signers in [minimal, validators] {
+            let mut measure = |operation, gas| {
+                measurements.push(Measurement {
+                    validators,
+                    signers,
+                    operation,
+                    gas,
+                })
+            };
+            let mut instance = mock_instance_with_options(
+                &code,
+                MockInstanceOptions {
+                    gas_limit: GAS_LIMIT,
+                    ..Default::default()
+                },
+            );
+            let env = mock_env();
+            measure(
+                "instantiate",
+                instantiate(
+                    &mut instance,
+                    &env,
+                    &InstantiateMsg {
+                        smart_contract_id: Addr::unchecked("contract0000"),
+                        valset: current.clone(),
+                    },
+                )?,
+            );
+            let signing = &validator_keys[..signers];
+            let payload = set_paused(&env, 1);
+            measure(
+                "set_paused",
+                execute(
+                    &mut instance,
+                    &env,
+                    &sign_payload(&current, &thresholds, signing, &payload)?,
+                )?,
+            );
+            let payload = set_paused(&env, 2);
+            let mut builder = ConsensusBuilder::new(&current, &payload, &thresholds)?;
+            for key in signing {
+                builder.sign(key)?;
+            }
+            measure(
+                "set_paused (compact)",
+                execute(&mut instance, &env, &builder.build_compact()?)?,
+            );
+            let payload = ExecutePayload::SubmitLogicCall {
+                logic_call_args: LogicCallArgs {
+                    contract_address: Addr::unchecked("target0000"),
+                    payload: r#"{"ping":{}}"#.to_string(),
+                    payload_binary: None,
+                    funds: vec![],
+                },
+                message_id: Uint256::from(3u8),
+                smart_contract_id: Addr::unchecked("contract0000"),
+                deadline: deadline(&env),
+                relayer_reward: None,
+            };
+            measure(
+                "submit_logic_call",
+                execute(
+                    &mut instance,
+                    &env,
+                    &sign_payload(&current, &thresholds, signing, &payload)?,
+                )?,
+            );
+            let payload = ExecutePayload::UpdateValset {
+                valset: valset(1, &keys(1, validators)),
+                smart_contract_id: Addr::unchecked("contract0000"),
+                deadline: Some(deadline(&env)),
+            };
+            measure(
+                "update_valset",
+                execute(
+                    &mut instance,
+                    &env,
+                    &sign_payload(&current, &thresholds, signing, &payload)?,
+                )?,
+            );
+        }
+    }
+
+    let report = report(&measurements);
+    print!("{report}");
+    if let Some(out) = out {
+        std::fs::write(&out, report).wrap_err_with(|| format!("writing {}", out.display()))?;
+    }
+    Ok(())
+}
+
+/// Builds compass-cw for deployment and returns the path of the wasm.
+fn build_wasm() -> Result<PathBuf> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+    let status = Command::new(root.join("scripts/build_release.sh"))
+        .arg("compass-cw")
+        .status()?;
+    if !status.success() {
+        bail!("Building compass-cw failed with {status}");
+    }
+    Ok(root.join("artifacts/compass_cw.wasm"))
+}
+
+/// Deterministic keys, so that runs are comparable.
+fn keys(valset_id: u8, validators: usize) -> Vec<SigningKey> {
+    (0..validators as u32)
+        .map(|index| {
+            let mut seed = [valset_id; 36];
+            seed[32..].copy_from_slice(&index.to_be_bytes());
+            SigningKey::from_bytes(&compass_cw::msg::payload_digest(&seed))
+                .expect("sha256 digests are valid keys")
+        })
+        .collect()
+}
+
+/// Valset of `keys` with equal powers.
+fn valset(valset_id: u8, keys: &[SigningKey]) -> Valset {
+    Valset {
+        valset_id: ValsetId(Uint256::from(valset_id)),
+        validators: keys.iter().map(|key| Binary(key.public_key())).collect(),
+        powers: vec![((1u64 << 32) / keys.len() as u64) as u32; keys.len()],
+        key_types: vec![],
+    }
+}
+
+fn deadline(env: &Env) -> Deadline {
+    Deadline::Time(env.block.time.seconds() + 60)
+}
+
+fn set_paused(env: &Env, message_id: u8) -> ExecutePayload {
+    ExecutePayload::SetPaused {
+        paused: PauseState::default(),
+        message_id: Uint256::from(message_id),
+        smart_contract_id: Addr::unchecked("contract0000"),
+        deadline: deadline(env),
+    }
+}
+
+/// Runs `call` and returns the gas it used, failing if the contract returned an error.
+fn gas_used(
+    instance: &mut VmInstance,
+    call: impl FnOnce(
+        &mut VmInstance,
+    ) -> cosmwasm_vm::VmResult<
+        cosmwasm_std::ContractResult<cosmwasm_std::Response<Empty>>,
+    >,
+) -> Result<u64> {
+    let before = instance.create_gas_report();
+    call(instance)?.into_result().map_err(|e| eyre!(e))?;
+    let after = instance.create_gas_report();
+    Ok(before.remaining - after.remaining)
+}
+
+fn instantiate(instance: &mut VmInstance, env: &Env, msg: &InstantiateMsg) -> Result<u64> {
+    let msg = serde_json::to_vec(msg)?;
+    gas_used(instance, |instance| {
+        call_instantiate(instance, env, &mock_info("admin0000", &[]), &msg)
+    })
+}
+
+fn execute(instance: &mut VmInstance, env: &Env, msg: &ExecuteMsg) -> Result<u64> {
+    let msg = serde_json::to_vec(msg)?;
+    gas_used(instance, |instance| {
+        call_execute(instance, env, &mock_info("relayer0000", &[]), &msg)
+    })
+}
+
+fn report(measurements: &[Measurement]) -> String {
+    let mut report = String::new();
+    let _ = writeln!(
+        report,
+        "| Validators | Signers | Operation | CosmWasm gas | SDK gas |"
+    );
+    let _ = writeln!(report, "|---:|---:|---|---:|---:|");
+    for measurement in measurements {
+        let _ = writeln!(
+            report,
+            "| {} | {} | {} | {} | {} |",
+            measurement.validators,
+            measurement.signers,
+            measurement.operation,
+            measurement.gas,
+            measurement.gas.div_ceil(GAS_MULTIPLIER),
+        );
+    }
+    report
+}
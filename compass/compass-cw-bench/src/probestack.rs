@@ -0,0 +1,12 @@
+//! `__rust_probestack`, for wasmer-vm to link with current Rust.
+//!
+//! The wasmer-vm 4.2 that cosmwasm-vm 1.5 pins declares `__rust_probestack` for the stack probes
+//! of code compiled by Cranelift, but Rust stopped exporting it when it switched to inline stack
+//! probes. cosmwasm-vm compiles with Singlepass unless its `cranelift` feature is enabled, and
+//! Singlepass never emits stack probes, so the symbol only needs to exist.
+
+/// Only called by code compiled by Cranelift.
+#[no_mangle]
+pub extern "C" fn __rust_probestack() {
+    std::process::abort()
+}
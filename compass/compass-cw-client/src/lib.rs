@@ -0,0 +1,218 @@
+//! Builds consensus messages for compass-cw.
+//!
+//! Validators sign `compass_cw::msg::payload_digest` of the serialized payload, the same function
+//! the contract verifies against, and their signatures are placed in valset order. Signing stops
+//! being useful once the signers hold the threshold of the payload, which `ConsensusBuilder`
+//! derives from compass' `Thresholds` with `Thresholds::for_payload`, like the contract does.
+
+use compass_cw::msg::{
+    payload_digest, CompactConsensus, Consensus, ExecuteMsg, ExecutePayload, KeyType, Signature,
+    Thresholds, Valset,
+};
+use cosmwasm_std::Binary;
+use k256::ecdsa::signature::hazmat::PrehashSigner;
+use thiserror::Error;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Error, Debug)]
+pub enum ClientError {
+    #[error("Payload can not be serialized: {0}")]
+    Payload(#[from] serde_json::Error),
+
+    #[error("Signing failed: {0}")]
+    Signing(String),
+
+    #[error("{public_key} is not a validator in valset {valset_id}")]
+    UnknownSigner {
+        public_key: String,
+        valset_id: String,
+    },
+
+    #[error("Insufficient power: signed {power} of the required {threshold}")]
+    InsufficientPower { power: u64, threshold: u64 },
+}
+
+/// A validator key able to sign payload digests.
+pub trait Signer {
+    /// Public key, as listed in the valset.
+    fn public_key(&self) -> Vec<u8>;
+
+    /// 64 byte signature of `digest`.
+    fn sign_digest(&self, digest: &[u8; 32]) -> Result<Vec<u8>, ClientError>;
+
+    /// Key type of the public key, as listed in the valset.
+    fn key_type(&self) -> KeyType {
+        KeyType::Secp256k1
+    }
+}
+
+impl Signer for k256::ecdsa::SigningKey {
+    fn public_key(&self) -> Vec<u8> {
+        self.verifying_key()
+            .to_encoded_point(true)
+            .as_bytes()
+            .to_vec()
+    }
+
+    fn sign_digest(&self, digest: &[u8; 32]) -> Result<Vec<u8>, ClientError> {
+        let signature: k256::ecdsa::Signature = self
+            .sign_prehash(digest)
+            .map_err(|e| ClientError::Signing(e.to_string()))?;
+        Ok(signature.normalize_s().unwrap_or(signature).to_vec())
+    }
+}
+
+impl Signer for secp256k1::SecretKey {
+    fn public_key(&self) -> Vec<u8> {
+        let secp = secp256k1::Secp256k1::signing_only();
+        secp256k1::PublicKey::from_secret_key(&secp, self)
+            .serialize()
+            .to_vec()
+    }
+
+    fn sign_digest(&self, digest: &[u8; 32]) -> Result<Vec<u8>, ClientError> {
+        let secp = secp256k1::Secp256k1::signing_only();
+        let message = secp256k1::Message::from_slice(digest)
+            .map_err(|e| ClientError::Signing(e.to_string()))?;
+        Ok(secp.sign_ecdsa(&message, self).serialize_compact().to_vec())
+    }
+}
+
+impl Signer for ed25519_zebra::SigningKey {
+    fn public_key(&self) -> Vec<u8> {
+        ed25519_zebra::VerificationKey::from(self).as_ref().to_vec()
+    }
+
+    fn sign_digest(&self, digest: &[u8; 32]) -> Result<Vec<u8>, ClientError> {
+        Ok(<[u8; 64]>::from(self.sign(digest)).to_vec())
+    }
+
+    fn key_type(&self) -> KeyType {
+        KeyType::Ed25519
+    }
+}
+
+/// Collects signatures of one payload from the validators of `valset`.
+pub struct ConsensusBuilder<'a> {
+    valset: &'a Valset,
+    payload: Vec<u8>,
+    digest: [u8; 32],
+    signatures: Vec<Option<Signature>>,
+    threshold: u64,
+}
+
+impl<'a> ConsensusBuilder<'a> {
+    /// Collects signatures of `payload` until they reach its threshold in `thresholds`, which
+    /// should be the `Thresholds` compass currently has.
+    pub fn new(
+        valset: &'a Valset,
+        payload: &ExecutePayload,
+        thresholds: &Thresholds,
+    ) -> Result<Self, ClientError> {
+        Ok(Self::with_bytes(
+            valset,
+            serde_json::to_vec(payload)?,
+            thresholds.for_payload(payload),
+        ))
+    }
+
+    /// Signs `payload` as is, e.g. when it was serialized by another party.
+    pub fn from_bytes(
+        valset: &'a Valset,
+        payload: Vec<u8>,
+        thresholds: &Thresholds,
+    ) -> Result<Self, ClientError> {
+        let threshold = thresholds.for_payload(&serde_json::from_slice(&payload)?);
+        Ok(Self::with_bytes(valset, payload, threshold))
+    }
+
+    fn with_bytes(valset: &'a Valset, payload: Vec<u8>, threshold: u64) -> Self {
+        ConsensusBuilder {
+            valset,
+            digest: payload_digest(&payload),
+            payload,
+            signatures: vec![None; valset.validators.len()],
+            threshold,
+        }
+    }
+
+    /// Adds the signature of `signer`, which must be a validator of the valset.
+    pub fn sign(&mut self, signer: &impl Signer) -> Result<&mut Self, ClientError> {
+        let public_key = signer.public_key();
+        let index = (0..self.valset.validators.len())
+            .position(|index| {
+                self.valset.validators[index].as_slice() == public_key
+                    && self.valset.key_type(index) == signer.key_type()
+            })
+            .ok_or_else(|| ClientError::UnknownSigner {
+                public_key: hex::encode(&public_key),
+                valset_id: self.valset.valset_id.0.to_string(),
+            })?;
+        self.signatures[index] = Some(Signature(signer.sign_digest(&self.digest)?));
+        Ok(self)
+    }
+
+    /// Power of the validators that signed so far.
+    pub fn power(&self) -> u64 {
+        self.signatures
+            .iter()
+            .zip(&self.valset.powers)
+            .filter(|(signature, _)| signature.is_some())
+            .map(|(_, &power)| power as u64)
+            .sum()
+    }
+
+    /// Whether compass will accept the signatures collected so far.
+    pub fn has_consensus(&self) -> bool {
+        self.power() >= self.threshold
+    }
+
+    pub fn build(self) -> Result<ExecuteMsg, ClientError> {
+        let (consensus, payload) = self.finish()?;
+        Ok(ExecuteMsg::Consensus {
+            consensus: consensus.into(),
+            payload,
+        })
+    }
+
+    /// Builds the message with a `CompactConsensus`, which is much smaller for large valsets.
+    pub fn build_compact(self) -> Result<ExecuteMsg, ClientError> {
+        let (consensus, payload) = self.finish()?;
+        Ok(ExecuteMsg::Consensus {
+            consensus: CompactConsensus::from(&consensus).into(),
+            payload,
+        })
+    }
+
+    fn finish(self) -> Result<(Consensus, Binary), ClientError> {
+        let power = self.power();
+        if power < self.threshold {
+            return Err(ClientError::InsufficientPower {
+                power,
+                threshold: self.threshold,
+            });
+        }
+        Ok((
+            Consensus {
+                signatures: self.signatures,
+            },
+            Binary(self.payload),
+        ))
+    }
+}
+
+/// Signs `payload` with every one of `signers` and builds the message relaying it.
+pub fn sign_payload<S: Signer>(
+    valset: &Valset,
+    thresholds: &Thresholds,
+    signers: &[S],
+    payload: &ExecutePayload,
+) -> Result<ExecuteMsg, ClientError> {
+    let mut builder = ConsensusBuilder::new(valset, payload, thresholds)?;
+    for signer in signers {
+        builder.sign(signer)?;
+    }
+    builder.build()
+}
@@ -0,0 +1,143 @@
+use compass_cw::contract::{execute, instantiate, query, POWER_THRESHOLD};
+use compass_cw::msg::{
+    ConsensusEncoding, Deadline, ExecuteMsg, ExecutePayload, InstantiateMsg, PauseState, QueryMsg,
+    Thresholds, Valset, ValsetId,
+};
+use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+use cosmwasm_std::{from_json, Addr, Binary, Uint256};
+use eyre::Result;
+use k256::ecdsa::SigningKey;
+use secp256k1::rand::rngs::OsRng;
+
+use crate::{sign_payload, ClientError, ConsensusBuilder, Signer};
+
+fn valset<S: Signer>(signers: &[S]) -> Valset {
+    Valset {
+        valset_id: ValsetId(Uint256::zero()),
+        validators: signers
+            .iter()
+            .map(|signer| Binary(signer.public_key()))
+            .collect(),
+        powers: vec![((1u64 << 32) / signers.len() as u64) as u32; signers.len()],
+        key_types: signers.iter().map(Signer::key_type).collect(),
+    }
+}
+
+fn pause(message_id: u8) -> ExecutePayload {
+    ExecutePayload::SetPaused {
+        paused: PauseState::default(),
+        message_id: Uint256::from(message_id),
+        smart_contract_id: Addr::unchecked("contract0000"),
+        deadline: Deadline::Time(mock_env().block.time.seconds() + 1),
+    }
+}
+
+fn thresholds(threshold: u64) -> Thresholds {
+    Thresholds {
+        update_valset: threshold,
+        logic_call: threshold,
+        fund_release: threshold,
+        bridge: threshold,
+        admin: threshold,
+        migrate: threshold,
+    }
+}
+
+fn assert_accepted<S: Signer>(keys: &[S]) -> Result<()> {
+    let valset = valset(keys);
+    let mut deps = mock_dependencies();
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("admin0000", &[]),
+        InstantiateMsg {
+            smart_contract_id: Addr::unchecked("contract0000"),
+            valset: valset.clone(),
+        },
+    )?;
+    let thresholds: Thresholds =
+        from_json(&query(deps.as_ref(), mock_env(), QueryMsg::Thresholds)?)?;
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("relayer0000", &[]),
+        sign_payload(&valset, &thresholds, keys, &pause(1))?,
+    )?;
+    let mut builder = ConsensusBuilder::new(&valset, &pause(2), &thresholds)?;
+    for key in keys {
+        builder.sign(key)?;
+    }
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("relayer0000", &[]),
+        builder.build_compact()?,
+    )?;
+    Ok(())
+}
+
+/// Messages built for either key type are accepted by compass.
+#[test]
+fn signed_messages_are_accepted() -> Result<()> {
+    let k256_keys: Vec<_> = (0..4).map(|_| SigningKey::random(&mut OsRng)).collect();
+    assert_accepted(&k256_keys)?;
+    let secp256k1_keys: Vec<_> = (0..4)
+        .map(|_| secp256k1::SecretKey::new(&mut OsRng))
+        .collect();
+    assert_accepted(&secp256k1_keys)?;
+    let ed25519_keys: Vec<_> = (0..4)
+        .map(|_| ed25519_zebra::SigningKey::new(OsRng))
+        .collect();
+    assert_accepted(&ed25519_keys)
+}
+
+#[test]
+fn power_accounting() -> Result<()> {
+    let keys: Vec<_> = (0..4).map(|_| SigningKey::random(&mut OsRng)).collect();
+    let valset = valset(&keys);
+    let payload = pause(1);
+    let defaults = thresholds(POWER_THRESHOLD);
+
+    let mut builder = ConsensusBuilder::new(&valset, &payload, &defaults)?;
+    builder.sign(&keys[3])?.sign(&keys[1])?;
+    // Signing twice does not count twice.
+    builder.sign(&keys[1])?;
+    assert_eq!(builder.power(), 2 * (1 << 30));
+    assert!(!builder.has_consensus());
+    assert!(matches!(
+        builder.build(),
+        Err(ClientError::InsufficientPower { power, .. }) if power == 2 * (1 << 30)
+    ));
+
+    let mut builder = ConsensusBuilder::new(
+        &valset,
+        &payload,
+        &Thresholds {
+            admin: 1 << 32,
+            ..defaults.clone()
+        },
+    )?;
+    builder.sign(&keys[3])?.sign(&keys[1])?.sign(&keys[0])?;
+    assert!(!builder.has_consensus());
+
+    let mut builder = ConsensusBuilder::new(&valset, &payload, &defaults)?;
+    builder.sign(&keys[3])?.sign(&keys[1])?.sign(&keys[0])?;
+    assert!(builder.has_consensus());
+    let msg = builder.build()?;
+    let ExecuteMsg::Consensus {
+        consensus: ConsensusEncoding::Full(consensus),
+        ..
+    } = msg
+    else {
+        panic!("Expected a full consensus.");
+    };
+    let signed: Vec<_> = consensus.signatures.iter().map(Option::is_some).collect();
+    assert_eq!(signed, [true, true, false, true]);
+
+    let stranger = SigningKey::random(&mut OsRng);
+    assert!(matches!(
+        ConsensusBuilder::new(&valset, &payload, &defaults)?.sign(&stranger),
+        Err(ClientError::UnknownSigner { .. })
+    ));
+    Ok(())
+}
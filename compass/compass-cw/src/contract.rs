@@ -1,105 +1,880 @@
-use crate::msg::{Consensus, ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg, Valset};
-use crate::msg::{LogicCallArgs, Signature};
-use crate::state::{MESSAGE_ID_USED, SMART_CONTRACT_ID, VALSET, VALSET_ID};
+use crate::error::{ContractError, ValsetError};
+use crate::msg::{
+    payload_digest, valset_hash, CompactConsensus, ConfigResponse, ConsensusEncoding, Deadline,
+    ExecuteMsg, ExecutePayload, Feature, InstantiateMsg, KeyType, MigrateMsg, NextNonce, NonceMode,
+    QueryMsg, Valset, ValsetLogEntry,
+};
+use crate::msg::{
+    AllowedTarget, BatchedLogicCall, Cw20HookMsg, LogicCallAction, LogicCallArgs, PauseState,
+    RelayerReward, Signature, TargetAllowlistResponse, Thresholds, UsedMessageId, UserMsg,
+    WrappedToken,
+};
+use crate::replay::{
+    check_deadline, message_id_record, next_nonce, nonce_record, use_message_id, used_nonces,
+};
+use crate::state::{
+    MessageIdRecord, Namespace, ALLOWED_TARGETS, FEE_POOL, NAMESPACES, ORDERED_TARGETS, PAUSED,
+    PENDING_WRAPPED_TOKEN, SMART_CONTRACT_ID, TARGET_ALLOWLIST_ENABLED, TARGET_USAGE, THRESHOLDS,
+    WRAPPED_TOKENS, WRAPPED_TOKEN_ORIGINS,
+};
+use crate::targets::{current_usage, use_target};
 use cosmwasm_std::{
-    to_binary, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdResult, Uint256, WasmMsg,
+    coin, ensure, ensure_eq, from_json, to_json_binary, Addr, BankMsg, Binary, BlockInfo, Coin,
+    CosmosMsg, Deps, DepsMut, Env, Event, MessageInfo, Order, Reply, Response, StdError, StdResult,
+    Storage, SubMsg, Uint128, Uint256, WasmMsg,
 };
-use eyre::{bail, ensure, Result};
+use cw2::{set_contract_version, CONTRACT};
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, MinterResponse};
+use cw_storage_plus::{Bound, Map};
 use itertools::izip;
-use ring::digest::{digest, SHA256};
+use std::borrow::Cow;
+use std::collections::HashMap;
 
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 
+const CONTRACT_NAME: &str = concat!("crates.io:", env!("CARGO_PKG_NAME"));
+const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Deployments made before versions were recorded with `cw2`.
+const UNVERSIONED: &str = "0.1.0";
+
 /// 2/3 * 2**32.
 /// Validator powers will be normalized to sum to 2**32 in every valset update.
-const POWER_THRESHOLD: u64 = 2_863_311_530;
+/// This is the default threshold of every kind of payload, see `Thresholds`.
+pub const POWER_THRESHOLD: u64 = 2_863_311_530;
+
+/// Thresholds must be more than half of the total power, and at most all of it.
+const MIN_THRESHOLD: u64 = 1 << 31;
+const MAX_THRESHOLD: u64 = 1 << 32;
+
+const DEPLOY_WRAPPED_TOKEN_REPLY_ID: u64 = 1;
+
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
+
+/// Number of valsets kept in the valset log of a namespace.
+const MAX_VALSET_LOG: usize = 100;
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn migrate(_deps: DepsMut, _env: Env, _msg: MigrateMsg) -> StdResult<Response> {
-    Ok(Response::new())
+pub fn migrate(deps: DepsMut, env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+    let from = match CONTRACT.may_load(deps.storage)? {
+        Some(info) => {
+            ensure_eq!(
+                info.contract,
+                CONTRACT_NAME,
+                ContractError::MigrateWrongContract {
+                    contract: info.contract
+                }
+            );
+            info.version
+        }
+        None => UNVERSIONED.to_string(),
+    };
+    match from.as_str() {
+        UNVERSIONED => migrate_from_unversioned(deps.storage, &env.block)?,
+        CONTRACT_VERSION => {}
+        _ => return Err(ContractError::MigrateUnknownVersion { version: from }),
+    }
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    Ok(Response::new()
+        .add_attribute("from_version", from)
+        .add_attribute("to_version", CONTRACT_VERSION))
+}
+
+/// Initializes state introduced after the first release.
+fn migrate_from_unversioned(
+    storage: &mut dyn Storage,
+    block: &BlockInfo,
+) -> Result<(), ContractError> {
+    PAUSED.save(storage, &PauseState::default())?;
+    TARGET_ALLOWLIST_ENABLED.save(storage, &false)?;
+    THRESHOLDS.save(storage, &default_thresholds())?;
+    let namespace = Namespace::instantiated(SMART_CONTRACT_ID.load(storage)?);
+    let valset = namespace.valset().load(storage)?;
+    log_valset(storage, &namespace, block, &valset)?;
+    Ok(())
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     _info: MessageInfo,
     msg: InstantiateMsg,
-) -> Result<Response> {
-    check_validator_power(&msg.valset.powers)?;
+) -> Result<Response, ContractError> {
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    let thresholds = default_thresholds();
+    validate_valset(&msg.valset, thresholds.max())?;
+    THRESHOLDS.save(deps.storage, &thresholds)?;
     SMART_CONTRACT_ID.save(deps.storage, &msg.smart_contract_id)?;
-    VALSET.save(deps.storage, &msg.valset)?;
-    VALSET_ID.save(deps.storage, &msg.valset.valset_id)?;
+    let namespace = Namespace::instantiated(msg.smart_contract_id);
+    namespace.valset().save(deps.storage, &msg.valset)?;
+    namespace
+        .valset_id()
+        .save(deps.storage, &msg.valset.valset_id)?;
+    log_valset(deps.storage, &namespace, &env.block, &msg.valset)?;
+    PAUSED.save(deps.storage, &PauseState::default())?;
+    TARGET_ALLOWLIST_ENABLED.save(deps.storage, &false)?;
     Ok(Response::new())
 }
 
 fn check_signature(
     deps: Deps,
+    index: usize,
+    key_type: KeyType,
     public_key: &[u8],
     message_hash: &[u8],
     sig: &Signature,
-) -> Result<()> {
+) -> Result<(), ContractError> {
+    let verified = match key_type {
+        KeyType::Secp256k1 => deps.api.secp256k1_verify(message_hash, &sig.0, public_key),
+        KeyType::Ed25519 => deps.api.ed25519_verify(message_hash, &sig.0, public_key),
+    };
     ensure!(
-        deps.api.secp256k1_verify(message_hash, &sig.0, public_key) == Ok(true),
-        "Invalid Signature"
+        verified == Ok(true),
+        ContractError::InvalidSignature { index }
     );
     Ok(())
 }
 
-fn check_validator_power(powers: &[u32]) -> Result<()> {
-    let mut cumulative_power: u64 = 0;
-    for &power in powers {
-        cumulative_power += power as u64;
-        if cumulative_power >= POWER_THRESHOLD {
+/// Verifies the `(index, public_key, signature)` Ed25519 signatures of `message_hash` as one
+/// batch, and one by one if the batch fails to find out which signature is invalid.
+fn check_ed25519_signatures(
+    deps: Deps,
+    message_hash: &[u8],
+    signed: &[(usize, &Binary, &Signature)],
+) -> Result<(), ContractError> {
+    if signed.len() > 1 {
+        let signatures: Vec<_> = signed.iter().map(|(_, _, sig)| sig.0.as_slice()).collect();
+        let public_keys: Vec<_> = signed.iter().map(|(_, key, _)| key.as_slice()).collect();
+        if deps
+            .api
+            .ed25519_batch_verify(&[message_hash], &signatures, &public_keys)
+            == Ok(true)
+        {
             return Ok(());
         }
     }
-    bail!("Insufficient Power");
+    for &(index, public_key, sig) in signed {
+        check_signature(deps, index, KeyType::Ed25519, public_key, message_hash, sig)?;
+    }
+    Ok(())
 }
 
-fn check_validator_signatures(deps: Deps, consensus: &Consensus, msg: &[u8]) -> Result<()> {
-    let valset = VALSET.load(deps.storage)?;
-    let hash = digest(&SHA256, msg);
+fn default_thresholds() -> Thresholds {
+    Thresholds {
+        update_valset: POWER_THRESHOLD,
+        logic_call: POWER_THRESHOLD,
+        fund_release: POWER_THRESHOLD,
+        bridge: POWER_THRESHOLD,
+        admin: POWER_THRESHOLD,
+        migrate: POWER_THRESHOLD,
+    }
+}
+
+/// Checks that validators, powers and key types line up, that every key is a distinct
+/// compressed secp256k1 point or 32 byte ed25519 key, and that the total power is at most
+/// 2**32 and reaches `threshold`.
+fn validate_valset(valset: &Valset, threshold: u64) -> Result<(), ValsetError> {
+    let Valset {
+        validators,
+        powers,
+        key_types,
+        ..
+    } = valset;
+    if validators.is_empty() {
+        return Err(ValsetError::Empty);
+    }
+    if validators.len() != powers.len() {
+        return Err(ValsetError::LengthMismatch {
+            validators: validators.len(),
+            powers: powers.len(),
+        });
+    }
+    if !key_types.is_empty() && key_types.len() != validators.len() {
+        return Err(ValsetError::KeyTypeMismatch {
+            validators: validators.len(),
+            key_types: key_types.len(),
+        });
+    }
+    let mut seen = HashMap::new();
+    for (index, key) in validators.iter().enumerate() {
+        let key_type = valset.key_type(index);
+        let valid = match key_type {
+            KeyType::Secp256k1 => key.len() == 33 && k256::PublicKey::from_sec1_bytes(key).is_ok(),
+            KeyType::Ed25519 => key.len() == 32,
+        };
+        if !valid {
+            return Err(ValsetError::InvalidKey { index, key_type });
+        }
+        if let Some(&first) = seen.get(key.as_slice()) {
+            return Err(ValsetError::DuplicateKey { index, first });
+        }
+        seen.insert(key.as_slice(), index);
+    }
+    let total: u64 = powers.iter().map(|&power| power as u64).sum();
+    if total > 1 << 32 {
+        return Err(ValsetError::PowerOverflow { total });
+    }
+    if total < threshold {
+        return Err(ValsetError::InsufficientPower { total, threshold });
+    }
+    Ok(())
+}
+
+/// Unpacks a `CompactConsensus` into one optional signature per validator.
+fn expand_consensus(
+    consensus: &ConsensusEncoding,
+    validators: usize,
+) -> Result<Cow<'_, [Option<Signature>]>, ContractError> {
+    let CompactConsensus {
+        signer_bitmap,
+        signatures,
+    } = match consensus {
+        ConsensusEncoding::Full(consensus) => return Ok(Cow::Borrowed(&consensus.signatures)),
+        ConsensusEncoding::Compact(consensus) => consensus,
+    };
+    let malformed = |reason: String| ContractError::MalformedConsensus { reason };
+    if signer_bitmap.len() != validators.div_ceil(8) {
+        return Err(malformed(format!(
+            "bitmap of {} bytes for {validators} validators",
+            signer_bitmap.len()
+        )));
+    }
+    let signed: Vec<_> = (0..signer_bitmap.len() * 8)
+        .map(|index| signer_bitmap[index / 8] & (1 << (index % 8)) != 0)
+        .collect();
+    if signed[validators..].contains(&true) {
+        return Err(malformed("bits set past the last validator".to_string()));
+    }
+    let signers = signed.iter().filter(|&&signed| signed).count();
+    let width = match signatures.len() {
+        len if signers > 0 && (len == signers * 64 || len == signers * 65) => len / signers,
+        0 if signers == 0 => 64,
+        len => {
+            return Err(malformed(format!(
+                "{len} signature bytes for {signers} signers"
+            )))
+        }
+    };
+    let mut chunks = signatures.chunks(width);
+    Ok(Cow::Owned(
+        signed[..validators]
+            .iter()
+            .map(|&signed| match signed {
+                true => chunks.next().map(|chunk| Signature(chunk[..64].to_vec())),
+                false => None,
+            })
+            .collect(),
+    ))
+}
+
+fn check_validator_signatures(
+    deps: Deps,
+    namespace: &Namespace,
+    consensus: &ConsensusEncoding,
+    msg: &[u8],
+    threshold: u64,
+) -> Result<(), ContractError> {
+    let valset = namespace.valset().load(deps.storage)?;
+    let signatures = expand_consensus(consensus, valset.validators.len())?;
+    if signatures.len() != valset.validators.len() {
+        return Err(ValsetError::SignatureCountMismatch {
+            signatures: signatures.len(),
+            validators: valset.validators.len(),
+        }
+        .into());
+    }
+    let hash = payload_digest(msg);
     let mut cumulative_power: u64 = 0;
-    for (validator, &power, sig) in izip!(&valset.validators, &valset.powers, &consensus.signatures)
+    // Ed25519 signatures are checked in one batch, once it is known which of them count.
+    let mut ed25519 = vec![];
+    for (index, (validator, &power, sig)) in
+        izip!(&valset.validators, &valset.powers, signatures.iter()).enumerate()
     {
         if let Some(sig) = sig {
-            check_signature(deps, validator, hash.as_ref(), sig)?;
+            match valset.key_type(index) {
+                KeyType::Secp256k1 => {
+                    check_signature(deps, index, KeyType::Secp256k1, validator, &hash, sig)?
+                }
+                KeyType::Ed25519 => ed25519.push((index, validator, sig)),
+            }
             cumulative_power += power as u64;
-            if cumulative_power >= POWER_THRESHOLD {
-                return Ok(());
+            if cumulative_power >= threshold {
+                return check_ed25519_signatures(deps, &hash, &ed25519);
             }
         }
     }
-    bail!("Insufficient Power");
+    check_ed25519_signatures(deps, &hash, &ed25519)?;
+    Err(ContractError::InsufficientPower {
+        power: cumulative_power,
+        threshold,
+    })
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> Result<Response> {
-    check_validator_signatures(deps.as_ref(), &msg.consensus, &msg.payload)?;
-    let payload = serde_json::from_slice(&msg.payload)?;
-    let id = SMART_CONTRACT_ID.load(deps.storage)?;
+pub fn execute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: ExecuteMsg,
+) -> Result<Response, ContractError> {
+    match msg {
+        ExecuteMsg::Consensus { consensus, payload } => {
+            execute_consensus(deps, env, info, &consensus, &payload)
+        }
+        ExecuteMsg::User(UserMsg::Receive(msg)) => receive_cw20(deps, env, info, msg),
+        ExecuteMsg::User(UserMsg::TopUpFeePool {}) => top_up_fee_pool(deps, info),
+    }
+}
+
+fn execute_consensus(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    consensus: &ConsensusEncoding,
+    payload: &[u8],
+) -> Result<Response, ContractError> {
+    let signed_payload = payload;
+    let payload: ExecutePayload =
+        serde_json::from_slice(payload).map_err(|e| ContractError::InvalidPayload {
+            reason: e.to_string(),
+        })?;
+    let namespace = namespace(deps.storage, payload.smart_contract_id())?;
+    ensure!(
+        namespace.is_instantiated() || !payload.is_contract_wide(),
+        ContractError::ContractWidePayload {
+            expected: SMART_CONTRACT_ID.load(deps.storage)?,
+            actual: namespace.smart_contract_id,
+        }
+    );
+    let threshold = THRESHOLDS.load(deps.storage)?.for_payload(&payload);
+    check_validator_signatures(
+        deps.as_ref(),
+        &namespace,
+        consensus,
+        signed_payload,
+        threshold,
+    )?;
 
     use crate::msg::ExecutePayload::*;
     match payload {
         UpdateValset {
             valset: new_valset,
-            smart_contract_id,
+            deadline,
+            ..
         } => {
-            ensure!(smart_contract_id == id, "Wrong smart contract instance");
-            update_valset(deps, env, info, &new_valset)
+            if let Some(deadline) = deadline {
+                check_deadline(&env.block, deadline)?;
+            }
+            update_valset(deps, &namespace, env, &new_valset)
         }
         SubmitLogicCall {
             logic_call_args,
             message_id,
-            smart_contract_id,
             deadline,
+            relayer_reward,
+            ..
+        } => {
+            check_relayer(deps.as_ref(), &env, &info, &relayer_reward)?;
+            check_spendable(deps.as_ref(), &env, &logic_call_args.funds)?;
+            let response = submit_logic_call(
+                deps.branch(),
+                &namespace,
+                env.clone(),
+                info.clone(),
+                logic_call_args,
+                message_id,
+                deadline,
+            )?;
+            pay_relayer(deps, info, relayer_reward, response)
+        }
+        SubmitLogicAction {
+            action,
+            message_id,
+            deadline,
+            relayer_reward,
+            ..
+        } => {
+            check_relayer(deps.as_ref(), &env, &info, &relayer_reward)?;
+            check_spendable(deps.as_ref(), &env, &action.funds())?;
+            let response = submit_logic_action(
+                deps.branch(),
+                &namespace,
+                env.clone(),
+                info.clone(),
+                action,
+                message_id,
+                deadline,
+            )?;
+            pay_relayer(deps, info, relayer_reward, response)
+        }
+        SubmitLogicCallBatch {
+            logic_calls,
+            deadline,
+            relayer_reward,
+            ..
+        } => {
+            check_relayer(deps.as_ref(), &env, &info, &relayer_reward)?;
+            ensure!(!logic_calls.is_empty(), ContractError::EmptyBatch);
+            let funds: Vec<_> = logic_calls
+                .iter()
+                .flat_map(|call| call.logic_call_args.funds.iter().cloned())
+                .collect();
+            check_spendable(deps.as_ref(), &env, &funds)?;
+            let mut response = Response::new();
+            for BatchedLogicCall {
+                logic_call_args,
+                message_id,
+            } in logic_calls
+            {
+                let call = submit_logic_call(
+                    deps.branch(),
+                    &namespace,
+                    env.clone(),
+                    info.clone(),
+                    logic_call_args,
+                    message_id,
+                    deadline,
+                )?;
+                response = response
+                    .add_submessages(call.messages)
+                    .add_events(call.events);
+            }
+            pay_relayer(deps, info, relayer_reward, response)
+        }
+        DeployWrappedToken {
+            origin_asset_id,
+            code_id,
+            name,
+            symbol,
+            decimals,
+            message_id,
+            deadline,
+            ..
+        } => deploy_wrapped_token(
+            deps,
+            &namespace,
+            env,
+            origin_asset_id,
+            code_id,
+            name,
+            symbol,
+            decimals,
+            message_id,
+            deadline,
+        ),
+        TransferWrappedToken {
+            origin_asset_id,
+            recipient,
+            amount,
+            message_id,
+            deadline,
+            relayer_reward,
+            ..
         } => {
-            ensure!(smart_contract_id == id, "Wrong smart contract instance");
-            submit_logic_call(deps, env, info, logic_call_args, message_id, deadline)
+            check_relayer(deps.as_ref(), &env, &info, &relayer_reward)?;
+            let response = transfer_wrapped_token(
+                deps.branch(),
+                &namespace,
+                env.clone(),
+                origin_asset_id,
+                recipient,
+                amount,
+                message_id,
+                deadline,
+            )?;
+            pay_relayer(deps, info, relayer_reward, response)
+        }
+        SetPaused {
+            paused,
+            message_id,
+            deadline,
+            ..
+        } => set_paused(deps, &namespace, env, paused, message_id, deadline),
+        UpdateTargetAllowlist {
+            enabled,
+            allow,
+            remove,
+            message_id,
+            deadline,
+            ..
+        } => update_target_allowlist(
+            deps, &namespace, env, enabled, allow, remove, message_id, deadline,
+        ),
+        SetNonceMode {
+            target,
+            mode,
+            message_id,
+            deadline,
+            ..
+        } => set_nonce_mode(deps, &namespace, env, target, mode, message_id, deadline),
+        SetThresholds {
+            thresholds,
+            message_id,
+            deadline,
+            ..
+        } => set_thresholds(deps, &namespace, env, thresholds, message_id, deadline),
+        Migrate {
+            new_code_id,
+            msg,
+            message_id,
+            deadline,
+            ..
+        } => {
+            use_message_id(
+                deps.storage,
+                &namespace,
+                &env.block,
+                message_id,
+                deadline,
+                Some(&env.contract.address),
+            )?;
+            Ok(Response::new()
+                .add_message(WasmMsg::Migrate {
+                    contract_addr: env.contract.address.into_string(),
+                    new_code_id,
+                    msg,
+                })
+                .add_attribute("new_code_id", new_code_id.to_string()))
+        }
+        CreateNamespace {
+            new_smart_contract_id,
+            valset,
+            message_id,
+            deadline,
+            ..
+        } => create_namespace(
+            deps,
+            &namespace,
+            env,
+            new_smart_contract_id,
+            &valset,
+            message_id,
+            deadline,
+        ),
+    }
+}
+
+/// The namespace of `smart_contract_id`, which compass must serve.
+fn namespace(storage: &dyn Storage, smart_contract_id: &Addr) -> Result<Namespace, ContractError> {
+    let instantiated = SMART_CONTRACT_ID.load(storage)?;
+    if *smart_contract_id == instantiated {
+        return Ok(Namespace::instantiated(instantiated));
+    }
+    ensure!(
+        NAMESPACES.has(storage, smart_contract_id),
+        ContractError::WrongSmartContractId {
+            expected: instantiated,
+            actual: smart_contract_id.clone(),
         }
+    );
+    Ok(Namespace::created(smart_contract_id.clone()))
+}
+
+/// Every namespace compass serves.
+fn namespaces(storage: &dyn Storage) -> StdResult<Vec<Namespace>> {
+    let mut namespaces = vec![Namespace::instantiated(SMART_CONTRACT_ID.load(storage)?)];
+    for smart_contract_id in NAMESPACES.keys(storage, None, None, Order::Ascending) {
+        namespaces.push(Namespace::created(smart_contract_id?));
+    }
+    Ok(namespaces)
+}
+
+fn create_namespace(
+    deps: DepsMut,
+    namespace: &Namespace,
+    env: Env,
+    smart_contract_id: Addr,
+    valset: &Valset,
+    message_id: Uint256,
+    deadline: Deadline,
+) -> Result<Response, ContractError> {
+    ensure!(
+        smart_contract_id != SMART_CONTRACT_ID.load(deps.storage)?
+            && !NAMESPACES.has(deps.storage, &smart_contract_id),
+        ContractError::NamespaceExists { smart_contract_id }
+    );
+    validate_valset(valset, THRESHOLDS.load(deps.storage)?.max())?;
+    use_message_id(
+        deps.storage,
+        namespace,
+        &env.block,
+        message_id,
+        deadline,
+        None,
+    )?;
+    NAMESPACES.save(deps.storage, &smart_contract_id, &())?;
+    let created = Namespace::created(smart_contract_id);
+    created.valset().save(deps.storage, valset)?;
+    created.valset_id().save(deps.storage, &valset.valset_id)?;
+    let entry = log_valset(deps.storage, &created, &env.block, valset)?;
+    Ok(Response::new().add_event(
+        Event::new("namespace_created")
+            .add_attribute("smart_contract_id", created.smart_contract_id)
+            .add_attribute("created_by", &namespace.smart_contract_id)
+            .add_attribute("valset_id", entry.valset_id.0.to_string())
+            .add_attribute("validators", entry.validators.to_string())
+            .add_attribute("total_power", entry.total_power.to_string())
+            .add_attribute("hash", entry.hash),
+    ))
+}
+
+fn set_paused(
+    deps: DepsMut,
+    namespace: &Namespace,
+    env: Env,
+    paused: PauseState,
+    message_id: Uint256,
+    deadline: Deadline,
+) -> Result<Response, ContractError> {
+    use_message_id(
+        deps.storage,
+        namespace,
+        &env.block,
+        message_id,
+        deadline,
+        None,
+    )?;
+    PAUSED.save(deps.storage, &paused)?;
+    Ok(Response::new().add_event(
+        Event::new("paused")
+            .add_attribute("logic_calls", paused.logic_calls.to_string())
+            .add_attribute("bridge", paused.bridge.to_string()),
+    ))
+}
+
+fn set_nonce_mode(
+    deps: DepsMut,
+    namespace: &Namespace,
+    env: Env,
+    target: Addr,
+    mode: NonceMode,
+    message_id: Uint256,
+    deadline: Deadline,
+) -> Result<Response, ContractError> {
+    use_message_id(
+        deps.storage,
+        namespace,
+        &env.block,
+        message_id,
+        deadline,
+        None,
+    )?;
+    match mode {
+        NonceMode::Unordered => ORDERED_TARGETS.remove(deps.storage, &target),
+        NonceMode::Ordered => ORDERED_TARGETS.save(deps.storage, &target, &())?,
     }
+    Ok(Response::new().add_event(
+        Event::new("nonce_mode")
+            .add_attribute("target", target)
+            .add_attribute("mode", mode.to_string()),
+    ))
+}
+
+/// Thresholds are kept above half of the total power, and within reach of the current valset of
+/// every namespace.
+fn set_thresholds(
+    deps: DepsMut,
+    namespace: &Namespace,
+    env: Env,
+    thresholds: Thresholds,
+    message_id: Uint256,
+    deadline: Deadline,
+) -> Result<Response, ContractError> {
+    for (name, threshold) in thresholds.entries() {
+        ensure!(
+            MIN_THRESHOLD < threshold && threshold <= MAX_THRESHOLD,
+            ContractError::InvalidThreshold {
+                name: name.to_string(),
+                threshold,
+                min: MIN_THRESHOLD,
+                max: MAX_THRESHOLD,
+            }
+        );
+    }
+    for namespace in namespaces(deps.storage)? {
+        let valset = namespace.valset().load(deps.storage)?;
+        let total: u64 = valset.powers.iter().map(|&power| power as u64).sum();
+        ensure!(
+            total >= thresholds.max(),
+            ValsetError::InsufficientPower {
+                total,
+                threshold: thresholds.max(),
+            }
+        );
+    }
+    use_message_id(
+        deps.storage,
+        namespace,
+        &env.block,
+        message_id,
+        deadline,
+        None,
+    )?;
+    THRESHOLDS.save(deps.storage, &thresholds)?;
+    let event = thresholds
+        .entries()
+        .into_iter()
+        .fold(Event::new("thresholds"), |event, (name, threshold)| {
+            event.add_attribute(name, threshold.to_string())
+        });
+    Ok(Response::new().add_event(event))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_target_allowlist(
+    deps: DepsMut,
+    namespace: &Namespace,
+    env: Env,
+    enabled: Option<bool>,
+    allow: Vec<AllowedTarget>,
+    remove: Vec<Addr>,
+    message_id: Uint256,
+    deadline: Deadline,
+) -> Result<Response, ContractError> {
+    for AllowedTarget { address, limits } in &allow {
+        ensure!(
+            limits.window > 0 || (limits.max_calls.is_none() && limits.max_funds.is_none()),
+            ContractError::InvalidTargetLimits {
+                target: address.clone()
+            }
+        );
+    }
+    use_message_id(
+        deps.storage,
+        namespace,
+        &env.block,
+        message_id,
+        deadline,
+        None,
+    )?;
+    if let Some(enabled) = enabled {
+        TARGET_ALLOWLIST_ENABLED.save(deps.storage, &enabled)?;
+    }
+    for address in &remove {
+        ALLOWED_TARGETS.remove(deps.storage, address);
+        TARGET_USAGE.remove(deps.storage, address);
+    }
+    for AllowedTarget { address, limits } in &allow {
+        ALLOWED_TARGETS.save(deps.storage, address, limits)?;
+    }
+    Ok(Response::new().add_event(
+        Event::new("target_allowlist_updated")
+            .add_attribute(
+                "enabled",
+                TARGET_ALLOWLIST_ENABLED.load(deps.storage)?.to_string(),
+            )
+            .add_attribute("allowed", allow.len().to_string())
+            .add_attribute("removed", remove.len().to_string()),
+    ))
+}
+
+/// Checks that compass holds `funds` on top of the relayer fee pool.
+fn check_spendable(deps: Deps, env: &Env, funds: &[Coin]) -> Result<(), ContractError> {
+    let mut required: Vec<Coin> = vec![];
+    for coin in funds {
+        match required.iter_mut().find(|total| total.denom == coin.denom) {
+            Some(total) => total.amount += coin.amount,
+            None => required.push(coin.clone()),
+        }
+    }
+    for Coin { denom, amount } in required {
+        let balance = deps
+            .querier
+            .query_balance(&env.contract.address, &denom)?
+            .amount;
+        let pool = FEE_POOL.may_load(deps.storage, &denom)?.unwrap_or_default();
+        let available = balance.saturating_sub(pool);
+        ensure!(
+            available >= amount,
+            ContractError::InsufficientFunds {
+                denom,
+                available,
+                required: amount,
+            }
+        );
+    }
+    Ok(())
+}
+
+/// Checks that the sender may claim the relayer reward, if any, and that the fee pool covers it.
+fn check_relayer(
+    deps: Deps,
+    env: &Env,
+    info: &MessageInfo,
+    reward: &Option<RelayerReward>,
+) -> Result<(), ContractError> {
+    let Some(RelayerReward {
+        reward,
+        exclusive_relayer,
+    }) = reward
+    else {
+        return Ok(());
+    };
+    // The bank module rejects sending zero coins.
+    ensure!(
+        !reward.amount.is_zero(),
+        ContractError::ZeroRelayerReward {
+            denom: reward.denom.clone(),
+        }
+    );
+    if let Some(exclusive) = exclusive_relayer {
+        ensure!(
+            info.sender == exclusive.address || env.block.time.seconds() >= exclusive.until,
+            ContractError::ReservedForRelayer {
+                relayer: exclusive.address.clone(),
+                until: exclusive.until,
+            }
+        );
+    }
+    let pool = FEE_POOL
+        .may_load(deps.storage, &reward.denom)?
+        .unwrap_or_default();
+    ensure!(
+        pool >= reward.amount,
+        ContractError::InsufficientFeePool {
+            denom: reward.denom.clone(),
+            available: pool,
+            required: reward.amount,
+        }
+    );
+    Ok(())
+}
+
+/// Pays the relayer reward, if any, out of the fee pool to the sender of the message.
+fn pay_relayer(
+    deps: DepsMut,
+    info: MessageInfo,
+    reward: Option<RelayerReward>,
+    response: Response,
+) -> Result<Response, ContractError> {
+    let Some(RelayerReward { reward, .. }) = reward else {
+        return Ok(response);
+    };
+    FEE_POOL.update(deps.storage, &reward.denom, |pool| {
+        StdResult::Ok(pool.unwrap_or_default().checked_sub(reward.amount)?)
+    })?;
+    Ok(response
+        .add_message(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: vec![reward.clone()],
+        })
+        .add_event(
+            Event::new("relayer_reward")
+                .add_attribute("relayer", info.sender)
+                .add_attribute("amount", reward.to_string()),
+        ))
+}
+
+fn top_up_fee_pool(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    ensure!(!info.funds.is_empty(), ContractError::NoFunds);
+    for Coin { denom, amount } in &info.funds {
+        FEE_POOL.update(deps.storage, denom, |pool| {
+            StdResult::Ok(pool.unwrap_or_default() + amount)
+        })?;
+    }
+    Ok(Response::new())
 }
 
 /// This updates the valset by checking that the validators in the current valset have signed off on the
@@ -110,61 +885,526 @@ pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> R
 /// consensus: current validator set and signatures
 fn update_valset(
     deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
+    namespace: &Namespace,
+    env: Env,
     new_valset: &Valset,
-) -> Result<Response> {
-    let valset_id = VALSET_ID.load(deps.storage)?;
+) -> Result<Response, ContractError> {
+    let valset_id = namespace.valset_id().load(deps.storage)?;
     ensure!(
         new_valset.valset_id > valset_id,
-        "Valset ID must be greater than the current valset ID"
+        ContractError::StaleValsetId {
+            current: valset_id,
+            new: new_valset.valset_id.clone(),
+        }
     );
-    check_validator_power(&new_valset.powers)?;
-    VALSET.save(deps.storage, new_valset)?;
-    VALSET_ID.save(deps.storage, &new_valset.valset_id)?;
-    Ok(Response::new())
+    validate_valset(new_valset, THRESHOLDS.load(deps.storage)?.max())?;
+    namespace.valset().save(deps.storage, new_valset)?;
+    namespace
+        .valset_id()
+        .save(deps.storage, &new_valset.valset_id)?;
+    let entry = log_valset(deps.storage, namespace, &env.block, new_valset)?;
+    Ok(Response::new().add_event(
+        Event::new("valset_updated")
+            .add_attribute("old_valset_id", valset_id.0.to_string())
+            .add_attribute("new_valset_id", entry.valset_id.0.to_string())
+            .add_attribute("validators", entry.validators.to_string())
+            .add_attribute("total_power", entry.total_power.to_string())
+            .add_attribute("hash", entry.hash),
+    ))
+}
+
+/// Records `valset` as active in `namespace` from `block`, forgetting the oldest valsets beyond
+/// `MAX_VALSET_LOG`.
+fn log_valset(
+    storage: &mut dyn Storage,
+    namespace: &Namespace,
+    block: &BlockInfo,
+    valset: &Valset,
+) -> StdResult<ValsetLogEntry> {
+    let entry = ValsetLogEntry {
+        valset_id: valset.valset_id.clone(),
+        validators: valset.validators.len() as u32,
+        total_power: valset.powers.iter().map(|&power| power as u64).sum(),
+        hash: hex::encode(valset_hash(valset)),
+        height: block.height,
+        time: block.time.seconds(),
+    };
+    let log = namespace.valset_log();
+    log.save(storage, &valset.valset_id.0.to_be_bytes(), &entry)?;
+    let expired = log
+        .keys(storage, None, None, Order::Descending)
+        .skip(MAX_VALSET_LOG)
+        .collect::<StdResult<Vec<_>>>()?;
+    for valset_id in expired {
+        log.remove(storage, &valset_id);
+    }
+    Ok(entry)
 }
 
 /// This makes calls to contracts that execute arbitrary logic
-/// message_id is to prevent replay attack and every message_id can be used only once
+/// message_id prevents replays until the deadline of the payload has passed, see `replay`
 fn submit_logic_call(
     deps: DepsMut,
+    namespace: &Namespace,
     env: Env,
     info: MessageInfo,
     args: LogicCallArgs,
     message_id: Uint256,
-    deadline: u64,
-) -> Result<Response> {
+    deadline: Deadline,
+) -> Result<Response, ContractError> {
+    submit_logic_action(
+        deps,
+        namespace,
+        env,
+        info,
+        LogicCallAction::Execute(args),
+        message_id,
+        deadline,
+    )
+}
+
+/// Sends the message described by `action` from compass.
+/// message_id prevents replays until the deadline of the payload has passed, see `replay`
+fn submit_logic_action(
+    deps: DepsMut,
+    namespace: &Namespace,
+    env: Env,
+    info: MessageInfo,
+    action: LogicCallAction,
+    message_id: Uint256,
+    deadline: Deadline,
+) -> Result<Response, ContractError> {
+    let target = action.target();
     ensure!(
-        args.contract_address != env.contract.address,
-        "Probable error, recursive compass invocation"
+        target.as_ref() != Some(&env.contract.address),
+        ContractError::RecursiveCall
+    );
+    ensure!(
+        !PAUSED.load(deps.storage)?.logic_calls,
+        ContractError::LogicCallsPaused
     );
-    ensure!(env.block.time.seconds() < deadline, "Timeout");
     ensure!(
         info.funds.iter().all(|coin| coin.amount.is_zero()),
-        "No funds should be sent to compass"
+        ContractError::UnexpectedFunds
+    );
+    if let LogicCallAction::Execute(args) = &action {
+        ensure!(
+            args.payload.is_empty() || args.payload_binary.is_none(),
+            ContractError::AmbiguousPayload
+        );
+        // Compass mints wrapped tokens, which logic calls must not do around the bridge.
+        ensure!(
+            !WRAPPED_TOKEN_ORIGINS.has(deps.storage, &args.contract_address),
+            ContractError::WrappedTokenTarget {
+                target: args.contract_address.clone(),
+            }
+        );
+    }
+    use_target(deps.storage, &env.block, &action)?;
+    use_message_id(
+        deps.storage,
+        namespace,
+        &env.block,
+        message_id,
+        deadline,
+        target.as_ref(),
+    )?;
+    let mut event = Event::new("logic_call").add_attribute("message_id", message_id.to_string());
+    if let LogicCallAction::Execute(args) = &action {
+        event = event.add_attribute("contract_address", &args.contract_address);
+    }
+    event = event.add_attribute("action", action.name());
+    if let Some(target) = target {
+        event = event.add_attribute("target", target);
+    }
+    Ok(Response::new()
+        .add_message(CosmosMsg::from(action))
+        .add_event(event))
+}
+
+/// Instantiates a cw20 with compass as its minter and admin. The new address is recorded in the
+/// registry once the instantiation succeeds, see `reply`.
+#[allow(clippy::too_many_arguments)]
+fn deploy_wrapped_token(
+    deps: DepsMut,
+    namespace: &Namespace,
+    env: Env,
+    origin_asset_id: String,
+    code_id: u64,
+    name: String,
+    symbol: String,
+    decimals: u8,
+    message_id: Uint256,
+    deadline: Deadline,
+) -> Result<Response, ContractError> {
+    ensure!(
+        !PAUSED.load(deps.storage)?.bridge,
+        ContractError::BridgePaused
+    );
+    ensure!(
+        !WRAPPED_TOKENS.has(deps.storage, &origin_asset_id),
+        ContractError::WrappedTokenExists { origin_asset_id }
     );
-    let message_id_bytes = message_id.to_be_bytes().to_vec();
+    use_message_id(
+        deps.storage,
+        namespace,
+        &env.block,
+        message_id,
+        deadline,
+        None,
+    )?;
+    PENDING_WRAPPED_TOKEN.save(deps.storage, &origin_asset_id)?;
+    let compass = env.contract.address.into_string();
+    let msg = cw20_base::msg::InstantiateMsg {
+        name,
+        symbol,
+        decimals,
+        initial_balances: vec![],
+        mint: Some(MinterResponse {
+            minter: compass.clone(),
+            cap: None,
+        }),
+        marketing: None,
+    };
+    Ok(Response::new().add_submessage(SubMsg::reply_on_success(
+        WasmMsg::Instantiate {
+            admin: Some(compass),
+            code_id,
+            msg: to_json_binary(&msg)?,
+            funds: vec![],
+            label: format!("compass wrapped {origin_asset_id}"),
+        },
+        DEPLOY_WRAPPED_TOKEN_REPLY_ID,
+    )))
+}
+
+/// Mints wrapped tokens for an asset locked on its origin chain.
+/// message_id prevents replays until the deadline of the payload has passed, see `replay`
+#[allow(clippy::too_many_arguments)]
+fn transfer_wrapped_token(
+    deps: DepsMut,
+    namespace: &Namespace,
+    env: Env,
+    origin_asset_id: String,
+    recipient: Addr,
+    amount: Uint128,
+    message_id: Uint256,
+    deadline: Deadline,
+) -> Result<Response, ContractError> {
     ensure!(
-        !MESSAGE_ID_USED.has(deps.storage, message_id_bytes.clone()),
-        "Used Message_ID"
+        !PAUSED.load(deps.storage)?.bridge,
+        ContractError::BridgePaused
+    );
+    let token = WRAPPED_TOKENS
+        .may_load(deps.storage, &origin_asset_id)?
+        .ok_or_else(|| ContractError::UnknownWrappedToken {
+            origin_asset_id: origin_asset_id.clone(),
+        })?;
+    use_message_id(
+        deps.storage,
+        namespace,
+        &env.block,
+        message_id,
+        deadline,
+        Some(&token),
+    )?;
+    Ok(Response::new()
+        .add_message(WasmMsg::Execute {
+            contract_addr: token.to_string(),
+            msg: to_json_binary(&Cw20ExecuteMsg::Mint {
+                recipient: recipient.to_string(),
+                amount,
+            })?,
+            funds: vec![],
+        })
+        .add_event(
+            Event::new("bridge_in")
+                .add_attribute("origin_asset_id", origin_asset_id)
+                .add_attribute("token", token)
+                .add_attribute("recipient", recipient)
+                .add_attribute("amount", amount),
+        ))
+}
+
+/// Wrapped tokens sent to compass are burned and an event is emitted for Paloma to release the
+/// original asset on its origin chain.
+fn receive_cw20(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    msg: Cw20ReceiveMsg,
+) -> Result<Response, ContractError> {
+    ensure!(
+        !PAUSED.load(deps.storage)?.bridge,
+        ContractError::BridgePaused
+    );
+    let origin_asset_id = WRAPPED_TOKEN_ORIGINS
+        .may_load(deps.storage, &info.sender)?
+        .ok_or_else(|| ContractError::NotWrappedToken {
+            address: info.sender.clone(),
+        })?;
+    let Cw20HookMsg::BridgeOut { recipient } = from_json(&msg.msg)?;
+    ensure!(!msg.amount.is_zero(), ContractError::ZeroAmount);
+    Ok(Response::new()
+        .add_message(WasmMsg::Execute {
+            contract_addr: info.sender.to_string(),
+            msg: to_json_binary(&Cw20ExecuteMsg::Burn { amount: msg.amount })?,
+            funds: vec![],
+        })
+        .add_event(
+            Event::new("bridge_out")
+                .add_attribute("origin_asset_id", origin_asset_id)
+                .add_attribute("token", info.sender)
+                .add_attribute("sender", msg.sender)
+                .add_attribute("recipient", recipient)
+                .add_attribute("amount", msg.amount),
+        ))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
+    ensure_eq!(
+        msg.id,
+        DEPLOY_WRAPPED_TOKEN_REPLY_ID,
+        ContractError::UnknownReplyId { id: msg.id }
     );
-    MESSAGE_ID_USED.save(deps.storage, message_id_bytes, &())?;
-    let LogicCallArgs {
-        contract_address: logic_contract_address,
-        payload,
-    } = args;
-    Ok(Response::new().add_message(WasmMsg::Execute {
-        contract_addr: logic_contract_address.into_string(),
-        msg: Binary(payload.into_bytes()),
-        funds: vec![],
-    }))
+    let response = msg
+        .result
+        .into_result()
+        .map_err(|reason| ContractError::SubMsgFailed { reason })?;
+    let address = response
+        .events
+        .iter()
+        .filter(|event| event.ty == "instantiate")
+        .flat_map(|event| &event.attributes)
+        .find(|attr| attr.key == "_contract_address")
+        .map(|attr| Addr::unchecked(&attr.value))
+        .ok_or(ContractError::MissingContractAddress)?;
+    let origin_asset_id = PENDING_WRAPPED_TOKEN.load(deps.storage)?;
+    PENDING_WRAPPED_TOKEN.remove(deps.storage);
+    WRAPPED_TOKENS.save(deps.storage, &origin_asset_id, &address)?;
+    WRAPPED_TOKEN_ORIGINS.save(deps.storage, &address, &origin_asset_id)?;
+    Ok(Response::new().add_event(
+        Event::new("wrapped_token_deployed")
+            .add_attribute("origin_asset_id", origin_asset_id)
+            .add_attribute("token", address),
+    ))
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
-        QueryMsg::SmartContractId => to_binary(&SMART_CONTRACT_ID.load(deps.storage)?),
-        QueryMsg::ValsetId => to_binary(&VALSET_ID.load(deps.storage)?),
+        QueryMsg::SmartContractId => to_json_binary(&SMART_CONTRACT_ID.load(deps.storage)?),
+        QueryMsg::ValsetId => to_json_binary(
+            &query_namespace(deps, None)?
+                .valset_id()
+                .load(deps.storage)?,
+        ),
+        QueryMsg::NamespaceValsetId { smart_contract_id } => to_json_binary(
+            &query_namespace(deps, Some(smart_contract_id))?
+                .valset_id()
+                .load(deps.storage)?,
+        ),
+        QueryMsg::Config { smart_contract_id } => {
+            let namespace = query_namespace(deps, smart_contract_id)?;
+            let valset = namespace.valset().load(deps.storage)?;
+            let has_any = |map: Map<&Addr, ()>| {
+                map.keys(deps.storage, None, None, Order::Ascending)
+                    .next()
+                    .is_some()
+            };
+            let features = [
+                (
+                    Feature::TargetAllowlist,
+                    TARGET_ALLOWLIST_ENABLED.load(deps.storage)?,
+                ),
+                (Feature::OrderedNonces, has_any(ORDERED_TARGETS)),
+                (Feature::Namespaces, has_any(NAMESPACES)),
+            ];
+            to_json_binary(&ConfigResponse {
+                valset_id: valset.valset_id,
+                validators: valset.validators.len() as u32,
+                total_power: valset.powers.iter().map(|&power| power as u64).sum(),
+                thresholds: THRESHOLDS.load(deps.storage)?,
+                version: cw2::get_contract_version(deps.storage)?,
+                paused: PAUSED.load(deps.storage)?,
+                features: features
+                    .into_iter()
+                    .filter_map(|(feature, enabled)| enabled.then_some(feature))
+                    .collect(),
+                smart_contract_id: namespace.smart_contract_id,
+            })
+        }
+        QueryMsg::WrappedToken { origin_asset_id } => {
+            to_json_binary(&WRAPPED_TOKENS.load(deps.storage, &origin_asset_id)?)
+        }
+        QueryMsg::WrappedTokens { start_after, limit } => {
+            let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+            let start = start_after.as_deref().map(Bound::exclusive);
+            let tokens = WRAPPED_TOKENS
+                .range(deps.storage, start, None, Order::Ascending)
+                .take(limit)
+                .map(|entry| {
+                    entry.map(|(origin_asset_id, address)| WrappedToken {
+                        origin_asset_id,
+                        address,
+                    })
+                })
+                .collect::<StdResult<Vec<_>>>()?;
+            to_json_binary(&tokens)
+        }
+        QueryMsg::FeePool => {
+            let pool = FEE_POOL
+                .range(deps.storage, None, None, Order::Ascending)
+                .map(|entry| entry.map(|(denom, amount)| coin(amount.u128(), denom)))
+                .collect::<StdResult<Vec<_>>>()?;
+            to_json_binary(&pool)
+        }
+        QueryMsg::Paused => to_json_binary(&PAUSED.load(deps.storage)?),
+        QueryMsg::Thresholds => to_json_binary(&THRESHOLDS.load(deps.storage)?),
+        QueryMsg::TargetAllowlist { start_after, limit } => {
+            let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+            let start = start_after.as_ref().map(Bound::exclusive);
+            let targets = ALLOWED_TARGETS
+                .range(deps.storage, start, None, Order::Ascending)
+                .take(limit)
+                .map(|entry| entry.map(|(address, limits)| AllowedTarget { address, limits }))
+                .collect::<StdResult<Vec<_>>>()?;
+            to_json_binary(&TargetAllowlistResponse {
+                enabled: TARGET_ALLOWLIST_ENABLED.load(deps.storage)?,
+                targets,
+            })
+        }
+        QueryMsg::TargetUsage { target } => {
+            let usage = ALLOWED_TARGETS
+                .may_load(deps.storage, &target)?
+                .map(|limits| current_usage(deps.storage, &env.block, &target, &limits))
+                .transpose()?;
+            to_json_binary(&usage)
+        }
+        QueryMsg::UsedMessageId {
+            message_id,
+            target,
+            smart_contract_id,
+        } => {
+            let namespace = query_namespace(deps, smart_contract_id)?;
+            let record = match target {
+                Some(target) => nonce_record(deps.storage, &namespace, &target, message_id)?,
+                None => message_id_record(deps.storage, &namespace, message_id)?,
+            };
+            to_json_binary(&record.map(|record| used_message_id(message_id, record)))
+        }
+        QueryMsg::UsedMessageIds {
+            start_after,
+            limit,
+            target: Some(target),
+            smart_contract_id,
+        } => {
+            let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+            let namespace = query_namespace(deps, smart_contract_id)?;
+            let used = used_nonces(deps.storage, &namespace, &target, start_after, limit)?
+                .into_iter()
+                .map(|(nonce, record)| used_message_id(nonce, record))
+                .collect::<Vec<_>>();
+            to_json_binary(&used)
+        }
+        QueryMsg::UsedMessageIds {
+            start_after,
+            limit,
+            target: None,
+            smart_contract_id,
+        } => {
+            let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+            let start_after = start_after.map(|message_id| message_id.to_be_bytes());
+            let start = start_after
+                .as_ref()
+                .map(|bytes| Bound::exclusive(&bytes[..]));
+            let used = query_namespace(deps, smart_contract_id)?
+                .message_id_used()
+                .range(deps.storage, start, None, Order::Ascending)
+                .take(limit)
+                .map(|entry| {
+                    let (message_id, record) = entry?;
+                    let message_id: [u8; 32] = message_id
+                        .try_into()
+                        .map_err(|key: Vec<u8>| StdError::invalid_data_size(32, key.len()))?;
+                    Ok(used_message_id(Uint256::from_be_bytes(message_id), record))
+                })
+                .collect::<StdResult<Vec<_>>>()?;
+            to_json_binary(&used)
+        }
+        QueryMsg::ValsetLog {
+            start_after,
+            limit,
+            smart_contract_id,
+        } => {
+            let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+            let start_after = start_after.map(|valset_id| valset_id.0.to_be_bytes());
+            let start = start_after
+                .as_ref()
+                .map(|bytes| Bound::exclusive(&bytes[..]));
+            let log = query_namespace(deps, smart_contract_id)?
+                .valset_log()
+                .range(deps.storage, start, None, Order::Ascending)
+                .take(limit)
+                .map(|entry| entry.map(|(_, entry)| entry))
+                .collect::<StdResult<Vec<_>>>()?;
+            to_json_binary(&log)
+        }
+        QueryMsg::Valset { smart_contract_id } => to_json_binary(
+            &query_namespace(deps, smart_contract_id)?
+                .valset()
+                .load(deps.storage)?,
+        ),
+        QueryMsg::NextNonce {
+            target,
+            smart_contract_id,
+        } => {
+            let namespace = query_namespace(deps, smart_contract_id)?;
+            let mode = match ORDERED_TARGETS.has(deps.storage, &target) {
+                true => NonceMode::Ordered,
+                false => NonceMode::Unordered,
+            };
+            to_json_binary(&NextNonce {
+                mode,
+                next_nonce: next_nonce(deps.storage, &namespace, &target)?,
+            })
+        }
+        QueryMsg::Namespaces { start_after, limit } => {
+            let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+            let start = start_after.as_ref().map(Bound::exclusive);
+            let namespaces = NAMESPACES
+                .keys(deps.storage, start, None, Order::Ascending)
+                .take(limit)
+                .collect::<StdResult<Vec<_>>>()?;
+            to_json_binary(&namespaces)
+        }
+    }
+}
+
+/// The namespace of `smart_contract_id`, by default the one compass was instantiated with.
+fn query_namespace(deps: Deps, smart_contract_id: Option<Addr>) -> StdResult<Namespace> {
+    match smart_contract_id {
+        Some(smart_contract_id) => namespace(deps.storage, &smart_contract_id)
+            .map_err(|e| StdError::not_found(e.to_string())),
+        None => Ok(Namespace::instantiated(
+            SMART_CONTRACT_ID.load(deps.storage)?,
+        )),
+    }
+}
+
+fn used_message_id(message_id: Uint256, record: MessageIdRecord) -> UsedMessageId {
+    let MessageIdRecord {
+        deadline,
+        height,
+        time,
+        target,
+    } = record;
+    UsedMessageId {
+        message_id,
+        deadline,
+        height,
+        time,
+        target,
     }
 }
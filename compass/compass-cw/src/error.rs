@@ -0,0 +1,221 @@
+use crate::msg::{KeyType, ValsetId};
+use cosmwasm_std::{Addr, StdError, Uint128, Uint256};
+use serde::{Serialize, Serializer};
+use thiserror::Error;
+
+/// Every way compass can fail. Variants and their fields are stable and serialize to snake_case
+/// JSON, so relayers and Paloma can decide whether to retry, re-sign or drop a message.
+#[derive(Error, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContractError {
+    #[error("{0}")]
+    Std(
+        #[from]
+        #[serde(serialize_with = "display")]
+        StdError,
+    ),
+
+    #[error("{0}")]
+    Valset(#[from] ValsetError),
+
+    #[error("Invalid payload: {reason}")]
+    InvalidPayload { reason: String },
+
+    #[error("Malformed compact consensus: {reason}")]
+    MalformedConsensus { reason: String },
+
+    #[error("Invalid signature from validator {index}")]
+    InvalidSignature { index: usize },
+
+    #[error("Insufficient power: signed {power} of the required {threshold}")]
+    InsufficientPower { power: u64, threshold: u64 },
+
+    #[error("Threshold {name} of {threshold} is outside of ({min}, {max}]")]
+    InvalidThreshold {
+        name: String,
+        threshold: u64,
+        min: u64,
+        max: u64,
+    },
+
+    #[error("Wrong smart contract instance {actual}, expected {expected}")]
+    WrongSmartContractId { expected: Addr, actual: Addr },
+
+    #[error(
+        "Only the valset of {expected} may sign payloads affecting every namespace, not {actual}"
+    )]
+    ContractWidePayload { expected: Addr, actual: Addr },
+
+    #[error("Namespace {smart_contract_id} already exists")]
+    NamespaceExists { smart_contract_id: Addr },
+
+    #[error("Timeout: deadline {deadline} has passed at block time {block_time}")]
+    Timeout { deadline: u64, block_time: u64 },
+
+    #[error(
+        "Deadline {deadline} is more than {max_horizon} seconds after block time {block_time}"
+    )]
+    DeadlineTooFar {
+        deadline: u64,
+        block_time: u64,
+        max_horizon: u64,
+    },
+
+    #[error("Timeout: deadline height {deadline_height} has been reached at {block_height}")]
+    HeightTimeout {
+        deadline_height: u64,
+        block_height: u64,
+    },
+
+    #[error(
+        "Deadline height {deadline_height} is more than {max_horizon} blocks after {block_height}"
+    )]
+    HeightDeadlineTooFar {
+        deadline_height: u64,
+        block_height: u64,
+        max_horizon: u64,
+    },
+
+    #[error("Deadline bounds neither time nor height")]
+    EmptyDeadline,
+
+    #[error("Message id {message_id} was already used")]
+    MessageIdUsed { message_id: Uint256 },
+
+    #[error("Nonce {actual} is out of order for {target}, expected {expected}")]
+    NonceOutOfOrder {
+        target: Addr,
+        expected: Uint256,
+        actual: Uint256,
+    },
+
+    #[error("Valset id {new:?} must be greater than the current valset id {current:?}")]
+    StaleValsetId { current: ValsetId, new: ValsetId },
+
+    #[error("Logic call batch is empty")]
+    EmptyBatch,
+
+    #[error("Logic call sets both payload and payload_binary")]
+    AmbiguousPayload,
+
+    #[error("Probable error, recursive compass invocation")]
+    RecursiveCall,
+
+    #[error("No funds should be sent to compass")]
+    UnexpectedFunds,
+
+    #[error("Logic calls are paused")]
+    LogicCallsPaused,
+
+    #[error("{target} is not an allowed logic call target")]
+    TargetNotAllowed { target: Addr },
+
+    #[error("{target} is a wrapped token, which only the bridge may call")]
+    WrappedTokenTarget { target: Addr },
+
+    #[error("{action} has no target, which the target allowlist does not permit")]
+    UntargetedAction { action: String },
+
+    #[error("Limits of {target} need a window")]
+    InvalidTargetLimits { target: Addr },
+
+    #[error("{target} may only be called {max_calls} times per {window} seconds")]
+    CallLimitExceeded {
+        target: Addr,
+        max_calls: u32,
+        window: u64,
+    },
+
+    #[error("{target} may only receive {limit}{denom} per window, {used}{denom} requested")]
+    FundsLimitExceeded {
+        target: Addr,
+        denom: String,
+        limit: Uint128,
+        used: Uint128,
+    },
+
+    #[error("Insufficient funds: {available}{denom} available, {required}{denom} required")]
+    InsufficientFunds {
+        denom: String,
+        available: Uint128,
+        required: Uint128,
+    },
+
+    #[error("Bridge is paused")]
+    BridgePaused,
+
+    #[error("Wrapped token for {origin_asset_id} already deployed")]
+    WrappedTokenExists { origin_asset_id: String },
+
+    #[error("Unknown wrapped token {origin_asset_id}")]
+    UnknownWrappedToken { origin_asset_id: String },
+
+    #[error("{address} is not a wrapped token")]
+    NotWrappedToken { address: Addr },
+
+    #[error("Nothing to bridge out")]
+    ZeroAmount,
+
+    #[error("Payload is reserved for relayer {relayer} until {until}")]
+    ReservedForRelayer { relayer: Addr, until: u64 },
+
+    #[error("Relayer reward of {denom} is zero")]
+    ZeroRelayerReward { denom: String },
+
+    #[error("Insufficient fee pool: {available}{denom} available, {required}{denom} required")]
+    InsufficientFeePool {
+        denom: String,
+        available: Uint128,
+        required: Uint128,
+    },
+
+    #[error("No funds sent")]
+    NoFunds,
+
+    #[error("Unknown reply id {id}")]
+    UnknownReplyId { id: u64 },
+
+    #[error("Submessage failed: {reason}")]
+    SubMsgFailed { reason: String },
+
+    #[error("Missing instantiated contract address")]
+    MissingContractAddress,
+
+    #[error("Cannot migrate from {contract}")]
+    MigrateWrongContract { contract: String },
+
+    #[error("Cannot migrate from version {version}")]
+    MigrateUnknownVersion { version: String },
+}
+
+/// Reasons a valset, or a consensus over it, is rejected.
+#[derive(Error, Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValsetError {
+    #[error("Valset has no validators")]
+    Empty,
+    #[error("Valset has {validators} validators but {powers} powers")]
+    LengthMismatch { validators: usize, powers: usize },
+    #[error("Valset has {validators} validators but {key_types} key types")]
+    KeyTypeMismatch { validators: usize, key_types: usize },
+    #[error("Validator {index} is not a {key_type} public key")]
+    InvalidKey { index: usize, key_type: KeyType },
+    #[error("Validator {index} duplicates validator {first}")]
+    DuplicateKey { index: usize, first: usize },
+    #[error("Valset power {total} exceeds 2^32")]
+    PowerOverflow { total: u64 },
+    #[error("Valset power {total} is below the threshold {threshold}")]
+    InsufficientPower { total: u64, threshold: u64 },
+    #[error("Consensus has {signatures} signatures for {validators} validators")]
+    SignatureCountMismatch {
+        signatures: usize,
+        validators: usize,
+    },
+}
+
+fn display<T: std::fmt::Display, S: Serializer>(
+    value: &T,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
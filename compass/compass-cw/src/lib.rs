@@ -1,8 +1,13 @@
 #![allow(clippy::derive_partial_eq_without_eq)]
 
 pub mod contract;
+pub mod error;
 pub mod msg;
+mod replay;
 mod state;
+mod targets;
 
+#[cfg(test)]
+mod proptests;
 #[cfg(test)]
 mod tests;
@@ -1,6 +1,12 @@
-use cosmwasm_std::{Addr, Binary, Uint256};
+use cosmwasm_std::{
+    Addr, BankMsg, Binary, Coin, CosmosMsg, IbcMsg, IbcTimeout, StakingMsg, Uint128, Uint256,
+    WasmMsg,
+};
+use cw20::Cw20ReceiveMsg;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct MigrateMsg {}
@@ -13,17 +19,113 @@ pub struct Valset {
     pub valset_id: ValsetId,
     pub validators: Vec<Binary>,
     pub powers: Vec<u32>,
+    /// The key type of each validator, all secp256k1 if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub key_types: Vec<KeyType>,
+}
+
+impl Valset {
+    pub fn key_type(&self, index: usize) -> KeyType {
+        self.key_types.get(index).copied().unwrap_or_default()
+    }
+}
+
+/// How a validator signs payload digests.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyType {
+    /// 33 byte compressed SEC1 public keys and 64 byte ECDSA signatures.
+    #[default]
+    Secp256k1,
+    /// 32 byte public keys and 64 byte signatures.
+    Ed25519,
+}
+
+impl fmt::Display for KeyType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            KeyType::Secp256k1 => "secp256k1",
+            KeyType::Ed25519 => "ed25519",
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Signature(pub Vec<u8>);
 
+/// The digest validators sign for a payload: the sha256 of its bytes, exactly as relayed in
+/// `ExecuteMsg::Consensus`.
+pub fn payload_digest(payload: &[u8]) -> [u8; 32] {
+    Sha256::digest(payload).into()
+}
+
+/// Identifies the contents of a valset: the sha256 of its 32 byte big endian id followed by
+/// every validator key and its 4 byte big endian power, in order. Ed25519 keys are preceded by a
+/// 0x01 byte, which no compressed secp256k1 key starts with.
+pub fn valset_hash(valset: &Valset) -> [u8; 32] {
+    let mut bytes = valset.valset_id.0.to_be_bytes().to_vec();
+    for (index, (validator, power)) in valset.validators.iter().zip(&valset.powers).enumerate() {
+        if valset.key_type(index) == KeyType::Ed25519 {
+            bytes.push(1);
+        }
+        bytes.extend_from_slice(validator);
+        bytes.extend_from_slice(&power.to_be_bytes());
+    }
+    payload_digest(&bytes)
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Consensus {
     /// Signatures must be in the same order as the validator array in `valset`
     pub signatures: Vec<Option<Signature>>,
 }
 
+/// `Consensus` packed for large valsets. Bit `i % 8` of byte `i / 8` of `signer_bitmap` is set
+/// if validator `i` signed, and `signatures` concatenates their signatures in valset order. All
+/// signatures are either 64 bytes, or 65 bytes with a trailing recovery id that is ignored.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CompactConsensus {
+    pub signer_bitmap: Binary,
+    pub signatures: Binary,
+}
+
+impl From<&Consensus> for CompactConsensus {
+    fn from(consensus: &Consensus) -> Self {
+        let mut signer_bitmap = vec![0u8; consensus.signatures.len().div_ceil(8)];
+        let mut signatures = vec![];
+        for (index, signature) in consensus.signatures.iter().enumerate() {
+            if let Some(signature) = signature {
+                signer_bitmap[index / 8] |= 1 << (index % 8);
+                signatures.extend_from_slice(&signature.0);
+            }
+        }
+        CompactConsensus {
+            signer_bitmap: Binary(signer_bitmap),
+            signatures: Binary(signatures),
+        }
+    }
+}
+
+/// Either encoding of the validator signatures over a payload.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(untagged)]
+pub enum ConsensusEncoding {
+    Full(Consensus),
+    Compact(CompactConsensus),
+}
+
+impl From<Consensus> for ConsensusEncoding {
+    fn from(consensus: Consensus) -> Self {
+        ConsensusEncoding::Full(consensus)
+    }
+}
+
+impl From<CompactConsensus> for ConsensusEncoding {
+    fn from(consensus: CompactConsensus) -> Self {
+        ConsensusEncoding::Compact(consensus)
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {
     pub smart_contract_id: Addr,
@@ -32,36 +134,734 @@ pub struct InstantiateMsg {
 
 pub type MessageId = Uint256;
 
+/// The block time in seconds, block height, or both, from which a payload is no longer valid.
+/// A bare number is a time, as in payloads signed before heights were supported.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, JsonSchema)]
+#[serde(untagged)]
+pub enum Deadline {
+    Time(u64),
+    Bounds {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        time: Option<u64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        height: Option<u64>,
+    },
+}
+
+impl Deadline {
+    /// The time and height bounds of the deadline.
+    pub fn bounds(self) -> (Option<u64>, Option<u64>) {
+        match self {
+            Deadline::Time(time) => (Some(time), None),
+            Deadline::Bounds { time, height } => (time, height),
+        }
+    }
+}
+
+impl From<u64> for Deadline {
+    fn from(time: u64) -> Self {
+        Deadline::Time(time)
+    }
+}
+
+/// Consensus messages keep their original untagged `{"consensus", "payload"}` shape so existing
+/// relayers are unaffected, everything else is tagged in `UserMsg`. `consensus` may also be a
+/// `CompactConsensus`.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
-pub struct ExecuteMsg {
-    pub consensus: Consensus,
-    pub payload: Binary,
+#[serde(untagged)]
+pub enum ExecuteMsg {
+    Consensus {
+        consensus: ConsensusEncoding,
+        payload: Binary,
+    },
+    User(UserMsg),
+}
+
+/// Messages that anyone may send without consensus.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum UserMsg {
+    /// cw20 receive hook. Only wrapped tokens minted by compass are accepted.
+    Receive(Cw20ReceiveMsg),
+    /// Adds the attached funds to the pool used to reward relayers.
+    TopUpFeePool {},
+}
+
+/// Messages carried in `Cw20ReceiveMsg.msg` when sending wrapped tokens to compass.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Cw20HookMsg {
+    /// Burn the sent tokens and release them to `recipient` on the origin chain.
+    BridgeOut { recipient: String },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecutePayload {
+    /// Replace the valset. Valset updates are not replayable since valset ids must increase,
+    /// but an optional deadline keeps a stale update from being applied much later.
     UpdateValset {
         valset: Valset,
         smart_contract_id: Addr,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        deadline: Option<Deadline>,
     },
     SubmitLogicCall {
         logic_call_args: LogicCallArgs,
         message_id: MessageId,
         smart_contract_id: Addr,
-        deadline: u64,
+        deadline: Deadline,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        relayer_reward: Option<RelayerReward>,
+    },
+    /// Take `action` on behalf of compass, under the same checks as `SubmitLogicCall`.
+    SubmitLogicAction {
+        action: LogicCallAction,
+        message_id: MessageId,
+        smart_contract_id: Addr,
+        deadline: Deadline,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        relayer_reward: Option<RelayerReward>,
+    },
+    /// Execute `logic_calls` in order under a single consensus. Every call still consumes its own
+    /// message id.
+    SubmitLogicCallBatch {
+        logic_calls: Vec<BatchedLogicCall>,
+        smart_contract_id: Addr,
+        deadline: Deadline,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        relayer_reward: Option<RelayerReward>,
+    },
+    /// Instantiate a cw20 with compass as minter to represent `origin_asset_id`.
+    DeployWrappedToken {
+        origin_asset_id: String,
+        code_id: u64,
+        name: String,
+        symbol: String,
+        decimals: u8,
+        message_id: MessageId,
+        smart_contract_id: Addr,
+        deadline: Deadline,
+    },
+    /// Mint `amount` of the wrapped `origin_asset_id` to `recipient`.
+    TransferWrappedToken {
+        origin_asset_id: String,
+        recipient: Addr,
+        amount: Uint128,
+        message_id: MessageId,
+        smart_contract_id: Addr,
+        deadline: Deadline,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        relayer_reward: Option<RelayerReward>,
+    },
+    /// Replace the pause state, e.g. to stop logic calls to a vulnerable target.
+    SetPaused {
+        paused: PauseState,
+        message_id: MessageId,
+        smart_contract_id: Addr,
+        deadline: Deadline,
+    },
+    /// Allow or remove logic call targets and switch the allowlist on or off. While it is
+    /// enabled, logic calls to contracts that are not allowed are rejected. The limits of an
+    /// allowed target apply whether or not the allowlist is enabled.
+    UpdateTargetAllowlist {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        enabled: Option<bool>,
+        #[serde(default)]
+        allow: Vec<AllowedTarget>,
+        #[serde(default)]
+        remove: Vec<Addr>,
+        message_id: MessageId,
+        smart_contract_id: Addr,
+        deadline: Deadline,
+    },
+    /// Switch how the message ids of payloads addressed to `target` are checked. Ordered targets
+    /// take ids as nonces, which must follow each other without gaps in every namespace.
+    SetNonceMode {
+        target: Addr,
+        mode: NonceMode,
+        message_id: MessageId,
+        smart_contract_id: Addr,
+        deadline: Deadline,
+    },
+    /// Replace the power thresholds. This needs the highest of the current thresholds.
+    SetThresholds {
+        thresholds: Thresholds,
+        message_id: MessageId,
+        smart_contract_id: Addr,
+        deadline: Deadline,
+    },
+    /// Migrate compass itself to `new_code_id`, passing `msg` to the new code's `migrate`.
+    /// This requires compass to be its own admin, which should be set right after instantiation.
+    Migrate {
+        new_code_id: u64,
+        msg: Binary,
+        message_id: MessageId,
+        smart_contract_id: Addr,
+        deadline: Deadline,
+    },
+    /// Serve another Paloma instance, whose payloads carry `new_smart_contract_id` and are signed
+    /// by `valset`. Namespaces have their own valsets and message ids, but share everything else,
+    /// including the funds held by compass, so this needs the highest of the current thresholds.
+    /// A created namespace can only update its valset and submit logic calls, see
+    /// `ExecutePayload::is_contract_wide`.
+    CreateNamespace {
+        new_smart_contract_id: Addr,
+        valset: Valset,
+        message_id: MessageId,
+        smart_contract_id: Addr,
+        deadline: Deadline,
     },
 }
 
+impl ExecutePayload {
+    /// The Paloma instance the payload is addressed to, whose valset must sign it.
+    pub fn smart_contract_id(&self) -> &Addr {
+        use ExecutePayload::*;
+        match self {
+            UpdateValset {
+                smart_contract_id, ..
+            }
+            | SubmitLogicCall {
+                smart_contract_id, ..
+            }
+            | SubmitLogicAction {
+                smart_contract_id, ..
+            }
+            | SubmitLogicCallBatch {
+                smart_contract_id, ..
+            }
+            | DeployWrappedToken {
+                smart_contract_id, ..
+            }
+            | TransferWrappedToken {
+                smart_contract_id, ..
+            }
+            | SetPaused {
+                smart_contract_id, ..
+            }
+            | UpdateTargetAllowlist {
+                smart_contract_id, ..
+            }
+            | SetNonceMode {
+                smart_contract_id, ..
+            }
+            | SetThresholds {
+                smart_contract_id, ..
+            }
+            | Migrate {
+                smart_contract_id, ..
+            }
+            | CreateNamespace {
+                smart_contract_id, ..
+            } => smart_contract_id,
+        }
+    }
+
+    /// Whether the payload changes state every namespace shares, so that only the valset of the
+    /// smart contract id compass was instantiated with may sign it.
+    pub fn is_contract_wide(&self) -> bool {
+        use ExecutePayload::*;
+        match self {
+            UpdateValset { .. }
+            | SubmitLogicCall { .. }
+            | SubmitLogicAction { .. }
+            | SubmitLogicCallBatch { .. } => false,
+            DeployWrappedToken { .. }
+            | TransferWrappedToken { .. }
+            | SetPaused { .. }
+            | UpdateTargetAllowlist { .. }
+            | SetNonceMode { .. }
+            | SetThresholds { .. }
+            | Migrate { .. }
+            | CreateNamespace { .. } => true,
+        }
+    }
+}
+
+/// Power, out of 2**32, that validators signing a payload must hold, by kind of payload. Every
+/// threshold is more than half of 2**32, and every valset must be able to reach all of them.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct Thresholds {
+    pub update_valset: u64,
+    /// Logic calls and actions that send no funds.
+    pub logic_call: u64,
+    /// Logic calls and actions sending funds out of compass.
+    pub fund_release: u64,
+    /// Minting of wrapped tokens.
+    pub bridge: u64,
+    /// Pausing, the target allowlist, nonce modes, wrapped token deployment and thresholds.
+    pub admin: u64,
+    pub migrate: u64,
+}
+
+impl Thresholds {
+    /// Every threshold with its name.
+    pub fn entries(&self) -> [(&'static str, u64); 6] {
+        [
+            ("update_valset", self.update_valset),
+            ("logic_call", self.logic_call),
+            ("fund_release", self.fund_release),
+            ("bridge", self.bridge),
+            ("admin", self.admin),
+            ("migrate", self.migrate),
+        ]
+    }
+
+    pub fn max(&self) -> u64 {
+        self.entries()
+            .into_iter()
+            .map(|(_, threshold)| threshold)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// The power that validators signing `payload` must hold.
+    pub fn for_payload(&self, payload: &ExecutePayload) -> u64 {
+        use ExecutePayload::*;
+        let logic_call = |funds: bool| match funds {
+            true => self.fund_release,
+            false => self.logic_call,
+        };
+        match payload {
+            UpdateValset { .. } => self.update_valset,
+            SubmitLogicCall {
+                logic_call_args, ..
+            } => logic_call(!logic_call_args.funds.is_empty()),
+            SubmitLogicAction { action, .. } => logic_call(!action.funds().is_empty()),
+            SubmitLogicCallBatch { logic_calls, .. } => logic_call(
+                logic_calls
+                    .iter()
+                    .any(|call| !call.logic_call_args.funds.is_empty()),
+            ),
+            TransferWrappedToken { .. } => self.bridge,
+            DeployWrappedToken { .. }
+            | SetPaused { .. }
+            | UpdateTargetAllowlist { .. }
+            | SetNonceMode { .. } => self.admin,
+            SetThresholds { .. } | CreateNamespace { .. } => self.max(),
+            Migrate { .. } => self.migrate,
+        }
+    }
+}
+
+/// Halts forwarding of the given flows. Valset updates are never paused.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+pub struct PauseState {
+    pub logic_calls: bool,
+    pub bridge: bool,
+}
+
+/// Paid from the fee pool to the sender of the `ExecuteMsg` once its payload is executed.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RelayerReward {
+    pub reward: Coin,
+    pub exclusive_relayer: Option<ExclusiveRelayer>,
+}
+
+/// Only `address` may relay the payload while the block time is before `until`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ExclusiveRelayer {
+    pub address: Addr,
+    pub until: u64,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct LogicCallArgs {
     pub contract_address: Addr,
+    /// Text message to execute, usually JSON.
+    #[serde(default)]
     pub payload: String,
+    /// Raw message to execute, base64 encoded. Only one of `payload` and `payload_binary` may
+    /// be set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload_binary: Option<Binary>,
+    /// Sent along with the call out of compass' own balance. The relayer fee pool can not be
+    /// spent this way.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub funds: Vec<Coin>,
+}
+
+/// Messages compass may send as a logic call. Funds are spent out of compass' own balance,
+/// never out of the relayer fee pool.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LogicCallAction {
+    Execute(LogicCallArgs),
+    BankSend {
+        to_address: Addr,
+        amount: Vec<Coin>,
+    },
+    IbcTransfer {
+        channel_id: String,
+        to_address: String,
+        amount: Coin,
+        timeout: IbcTimeout,
+    },
+    Instantiate {
+        admin: Option<Addr>,
+        code_id: u64,
+        msg: Binary,
+        #[serde(default)]
+        funds: Vec<Coin>,
+        label: String,
+    },
+    Instantiate2 {
+        admin: Option<Addr>,
+        code_id: u64,
+        msg: Binary,
+        #[serde(default)]
+        funds: Vec<Coin>,
+        label: String,
+        salt: Binary,
+    },
+    Delegate {
+        validator: String,
+        amount: Coin,
+    },
+    Undelegate {
+        validator: String,
+        amount: Coin,
+    },
+    Redelegate {
+        src_validator: String,
+        dst_validator: String,
+        amount: Coin,
+    },
+}
+
+impl LogicCallAction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LogicCallAction::Execute(_) => "execute",
+            LogicCallAction::BankSend { .. } => "bank_send",
+            LogicCallAction::IbcTransfer { .. } => "ibc_transfer",
+            LogicCallAction::Instantiate { .. } => "instantiate",
+            LogicCallAction::Instantiate2 { .. } => "instantiate2",
+            LogicCallAction::Delegate { .. } => "delegate",
+            LogicCallAction::Undelegate { .. } => "undelegate",
+            LogicCallAction::Redelegate { .. } => "redelegate",
+        }
+    }
+
+    /// The address the allowlist and its limits apply to. Actions without one can only be taken
+    /// while the allowlist is disabled.
+    pub fn target(&self) -> Option<Addr> {
+        match self {
+            LogicCallAction::Execute(args) => Some(args.contract_address.clone()),
+            LogicCallAction::BankSend { to_address, .. } => Some(to_address.clone()),
+            LogicCallAction::Delegate { validator, .. }
+            | LogicCallAction::Undelegate { validator, .. } => Some(Addr::unchecked(validator)),
+            LogicCallAction::Redelegate { dst_validator, .. } => {
+                Some(Addr::unchecked(dst_validator))
+            }
+            LogicCallAction::IbcTransfer { .. }
+            | LogicCallAction::Instantiate { .. }
+            | LogicCallAction::Instantiate2 { .. } => None,
+        }
+    }
+
+    /// Funds leaving compass' balance.
+    pub fn funds(&self) -> Vec<Coin> {
+        match self {
+            LogicCallAction::Execute(args) => args.funds.clone(),
+            LogicCallAction::BankSend { amount, .. } => amount.clone(),
+            LogicCallAction::Instantiate { funds, .. }
+            | LogicCallAction::Instantiate2 { funds, .. } => funds.clone(),
+            LogicCallAction::IbcTransfer { amount, .. }
+            | LogicCallAction::Delegate { amount, .. } => vec![amount.clone()],
+            LogicCallAction::Undelegate { .. } | LogicCallAction::Redelegate { .. } => vec![],
+        }
+    }
+}
+
+impl From<LogicCallAction> for CosmosMsg {
+    fn from(action: LogicCallAction) -> Self {
+        match action {
+            LogicCallAction::Execute(LogicCallArgs {
+                contract_address,
+                payload,
+                payload_binary,
+                funds,
+            }) => WasmMsg::Execute {
+                contract_addr: contract_address.into_string(),
+                msg: payload_binary.unwrap_or_else(|| Binary(payload.into_bytes())),
+                funds,
+            }
+            .into(),
+            LogicCallAction::BankSend { to_address, amount } => BankMsg::Send {
+                to_address: to_address.into_string(),
+                amount,
+            }
+            .into(),
+            LogicCallAction::IbcTransfer {
+                channel_id,
+                to_address,
+                amount,
+                timeout,
+            } => IbcMsg::Transfer {
+                channel_id,
+                to_address,
+                amount,
+                timeout,
+            }
+            .into(),
+            LogicCallAction::Instantiate {
+                admin,
+                code_id,
+                msg,
+                funds,
+                label,
+            } => WasmMsg::Instantiate {
+                admin: admin.map(Addr::into_string),
+                code_id,
+                msg,
+                funds,
+                label,
+            }
+            .into(),
+            LogicCallAction::Instantiate2 {
+                admin,
+                code_id,
+                msg,
+                funds,
+                label,
+                salt,
+            } => WasmMsg::Instantiate2 {
+                admin: admin.map(Addr::into_string),
+                code_id,
+                label,
+                msg,
+                funds,
+                salt,
+            }
+            .into(),
+            LogicCallAction::Delegate { validator, amount } => {
+                StakingMsg::Delegate { validator, amount }.into()
+            }
+            LogicCallAction::Undelegate { validator, amount } => {
+                StakingMsg::Undelegate { validator, amount }.into()
+            }
+            LogicCallAction::Redelegate {
+                src_validator,
+                dst_validator,
+                amount,
+            } => StakingMsg::Redelegate {
+                src_validator,
+                dst_validator,
+                amount,
+            }
+            .into(),
+        }
+    }
+}
+
+/// A contract logic calls may target, and the limits on calling it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AllowedTarget {
+    pub address: Addr,
+    #[serde(default)]
+    pub limits: TargetLimits,
+}
+
+/// Limits on the logic calls to a target within a window of `window` seconds. A window starts
+/// with the first call after the previous window has ended.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+pub struct TargetLimits {
+    pub window: u64,
+    pub max_calls: Option<u32>,
+    /// Funds that may be sent to the target per window. Denoms that are not listed may not be
+    /// sent at all, while `None` leaves funds unlimited.
+    pub max_funds: Option<Vec<Coin>>,
+}
+
+/// How the message ids of payloads addressed to a target are checked.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NonceMode {
+    /// Any id that was not used before, in any order.
+    #[default]
+    Unordered,
+    /// The next nonce of the target, starting from zero.
+    Ordered,
+}
+
+impl fmt::Display for NonceMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            NonceMode::Unordered => "unordered",
+            NonceMode::Ordered => "ordered",
+        })
+    }
+}
+
+/// Logic calls made to a target, and funds sent to it, in its current window.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TargetUsage {
+    pub window_start: u64,
+    pub calls: u32,
+    pub funds: Vec<Coin>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TargetAllowlistResponse {
+    pub enabled: bool,
+    pub targets: Vec<AllowedTarget>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BatchedLogicCall {
+    pub logic_call_args: LogicCallArgs,
+    pub message_id: MessageId,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
     SmartContractId,
+    /// Returns the valset id of the namespace compass was instantiated with.
     ValsetId,
+    /// Returns the valset id of the namespace of `smart_contract_id`.
+    NamespaceValsetId {
+        smart_contract_id: Addr,
+    },
+    /// Returns a `ConfigResponse` summing up the deployment as seen by a namespace.
+    Config {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        smart_contract_id: Option<Addr>,
+    },
+    /// Returns the cw20 address of the wrapped `origin_asset_id`.
+    WrappedToken {
+        origin_asset_id: String,
+    },
+    /// Returns a page of `WrappedToken`s ordered by origin asset id.
+    WrappedTokens {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Returns the relayer fee pool as a list of coins.
+    FeePool,
+    /// Returns the current `PauseState`.
+    Paused,
+    /// Returns the current `Thresholds`.
+    Thresholds,
+    /// Returns a `TargetAllowlistResponse` with a page of allowed targets ordered by address.
+    TargetAllowlist {
+        start_after: Option<Addr>,
+        limit: Option<u32>,
+    },
+    /// Returns the `TargetUsage` of an allowed target, or null for any other address.
+    TargetUsage {
+        target: Addr,
+    },
+    /// Returns the `UsedMessageId` of `message_id`, or null if it is unused. Ids are forgotten
+    /// some time after their deadline has passed, since their payloads can not execute anymore.
+    /// With a `target`, `message_id` is looked up as a nonce the target used while ordered.
+    /// Like the other queries taking a `smart_contract_id`, this defaults to the namespace
+    /// compass was instantiated with.
+    UsedMessageId {
+        message_id: MessageId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        target: Option<Addr>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        smart_contract_id: Option<Addr>,
+    },
+    /// Returns a page of `UsedMessageId`s ordered by message id, or of the nonces `target` used
+    /// while ordered. Ids used by unversioned deployments are only returned by `UsedMessageId`.
+    UsedMessageIds {
+        start_after: Option<MessageId>,
+        limit: Option<u32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        target: Option<Addr>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        smart_contract_id: Option<Addr>,
+    },
+    /// Returns a page of `ValsetLogEntry`s ordered by valset id. Only the most recent valsets
+    /// are kept.
+    ValsetLog {
+        start_after: Option<ValsetId>,
+        limit: Option<u32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        smart_contract_id: Option<Addr>,
+    },
+    /// Returns the current `Valset` of a namespace.
+    Valset {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        smart_contract_id: Option<Addr>,
+    },
+    /// Returns the `NextNonce` of `target`, which is only checked while the target is ordered.
+    NextNonce {
+        target: Addr,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        smart_contract_id: Option<Addr>,
+    },
+    /// Returns a page of the smart contract ids of namespaces created after instantiation, in
+    /// order.
+    Namespaces {
+        start_after: Option<Addr>,
+        limit: Option<u32>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NextNonce {
+    pub mode: NonceMode,
+    /// The message id the next payload addressed to the target must carry in ordered mode.
+    pub next_nonce: MessageId,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ConfigResponse {
+    pub smart_contract_id: Addr,
+    pub valset_id: ValsetId,
+    pub validators: u32,
+    pub total_power: u64,
+    pub thresholds: Thresholds,
+    /// The `cw2` name and version of the contract code.
+    pub version: cw2::ContractVersion,
+    pub paused: PauseState,
+    pub features: Vec<Feature>,
+}
+
+/// Optional behaviour that consensus switched on for a deployment.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    /// Logic calls are restricted to allowed targets.
+    TargetAllowlist,
+    /// Some targets take their message ids as ordered nonces.
+    OrderedNonces,
+    /// Compass serves Paloma instances besides the one it was instantiated with.
+    Namespaces,
+}
+
+/// A valset that became active, and when.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ValsetLogEntry {
+    pub valset_id: ValsetId,
+    pub validators: u32,
+    pub total_power: u64,
+    /// Hex encoded `valset_hash`.
+    pub hash: String,
+    /// Deployments predating the log record their valset at the height they were migrated.
+    pub height: u64,
+    pub time: u64,
+}
+
+/// A message id and when it was used, if known. Ids used by unversioned deployments only have a
+/// deadline, which never passes.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UsedMessageId {
+    pub message_id: MessageId,
+    pub deadline: Deadline,
+    pub height: Option<u64>,
+    pub time: Option<u64>,
+    /// The contract the payload was addressed to, if any.
+    pub target: Option<Addr>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct WrappedToken {
+    pub origin_asset_id: String,
+    pub address: Addr,
 }
@@ -0,0 +1,357 @@
+//! Property based checks of consensus and replay handling, and a fuzz harness over message
+//! parsing. Every runner is seeded so failures reproduce across machines.
+
+use cosmwasm_std::testing::{
+    mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage,
+};
+use cosmwasm_std::{from_json, Addr, Binary, OwnedDeps, Uint256};
+use proptest::prelude::*;
+use proptest::strategy::ValueTree;
+use proptest::test_runner::{Config, RngAlgorithm, TestRng, TestRunner};
+use secp256k1::{Message, Secp256k1, SecretKey};
+use std::collections::HashSet;
+
+use crate::contract::{execute, instantiate, POWER_THRESHOLD};
+use crate::error::ContractError;
+use crate::msg::{
+    CompactConsensus, Consensus, ConsensusEncoding, Deadline, ExecuteMsg, ExecutePayload,
+    InstantiateMsg, PauseState, Signature, Valset, ValsetId,
+};
+use crate::replay::use_message_id;
+use crate::state::Namespace;
+
+type TestDeps = OwnedDeps<MockStorage, MockApi, MockQuerier>;
+
+/// A runner whose cases only depend on `seed`.
+fn runner(seed: u8, cases: u32) -> TestRunner {
+    TestRunner::new_with_rng(
+        Config {
+            cases,
+            failure_persistence: None,
+            ..Config::default()
+        },
+        TestRng::from_seed(RngAlgorithm::ChaCha, &[seed; 32]),
+    )
+}
+
+/// Distinct validator keys, derived from the generated bytes so that shrinking reproduces them.
+fn secret_keys(
+    validators: impl Into<prop::collection::SizeRange>,
+) -> BoxedStrategy<Vec<SecretKey>> {
+    prop::collection::hash_set(any::<[u8; 32]>(), validators)
+        .prop_filter_map("not a secret key", |seeds| {
+            seeds
+                .iter()
+                .map(|seed| SecretKey::from_slice(seed).ok())
+                .collect()
+        })
+        .boxed()
+}
+
+/// Powers proportional to `weights`, normalized to sum to at most 2**32 as Paloma does.
+fn normalize(weights: &[u32]) -> Vec<u32> {
+    let total: u64 = weights.iter().map(|&weight| weight as u64).sum();
+    weights
+        .iter()
+        .map(|&weight| ((weight as u64) << 32).checked_div(total).unwrap_or(0) as u32)
+        .collect()
+}
+
+fn setup(sks: &[SecretKey], powers: Vec<u32>) -> Result<TestDeps, ContractError> {
+    let secp = Secp256k1::new();
+    let mut deps = mock_dependencies();
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("admin0000", &[]),
+        InstantiateMsg {
+            smart_contract_id: Addr::unchecked("contract0000"),
+            valset: Valset {
+                valset_id: ValsetId(Uint256::zero()),
+                validators: sks
+                    .iter()
+                    .map(|sk| Binary(sk.public_key(&secp).serialize().to_vec()))
+                    .collect(),
+                powers,
+                key_types: vec![],
+            },
+        },
+    )?;
+    Ok(deps)
+}
+
+fn set_paused(message_id: u64) -> ExecutePayload {
+    ExecutePayload::SetPaused {
+        paused: PauseState::default(),
+        message_id: Uint256::from(message_id),
+        smart_contract_id: Addr::unchecked("contract0000"),
+        deadline: Deadline::Time(mock_env().block.time.seconds() + 1),
+    }
+}
+
+/// Signatures of `payload` by the validators flagged in `signers`.
+fn sign(sks: &[SecretKey], signers: &[bool], payload: &[u8]) -> Consensus {
+    let secp = Secp256k1::new();
+    let hash = Message::from_slice(&crate::msg::payload_digest(payload)).unwrap();
+    Consensus {
+        signatures: sks
+            .iter()
+            .zip(signers)
+            .map(|(sk, &signed)| {
+                signed.then(|| Signature(secp.sign_ecdsa(&hash, sk).serialize_compact().to_vec()))
+            })
+            .collect(),
+    }
+}
+
+fn relay(
+    deps: &mut TestDeps,
+    consensus: ConsensusEncoding,
+    payload: &[u8],
+) -> Result<(), ContractError> {
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("relayer0000", &[]),
+        ExecuteMsg::Consensus {
+            consensus,
+            payload: Binary(payload.to_vec()),
+        },
+    )
+    .map(|_| ())
+}
+
+/// Validators with their weights and whether each of them signs.
+fn signed_valsets() -> impl Strategy<Value = (Vec<SecretKey>, Vec<u32>, Vec<bool>)> {
+    secret_keys(1..24).prop_flat_map(|sks| {
+        let n = sks.len();
+        (
+            Just(sks),
+            prop::collection::vec(0u32..1000, n).prop_filter("no power", |weights| {
+                weights.iter().any(|&weight| weight > 0)
+            }),
+            prop::collection::vec(any::<bool>(), n),
+        )
+    })
+}
+
+#[test]
+fn executes_iff_signers_reach_threshold() {
+    runner(1, 128)
+        .run(
+            &(signed_valsets(), any::<bool>(), any::<u64>()),
+            |((sks, weights, signers), compact, message_id)| {
+                let powers = normalize(&weights);
+                let deps = setup(&sks, powers.clone());
+                let total: u64 = powers.iter().map(|&power| power as u64).sum();
+                prop_assert_eq!(deps.is_ok(), total >= POWER_THRESHOLD);
+                let Ok(mut deps) = deps else {
+                    return Ok(());
+                };
+                let signed: u64 = powers
+                    .iter()
+                    .zip(&signers)
+                    .filter(|(_, &signed)| signed)
+                    .map(|(&power, _)| power as u64)
+                    .sum();
+                let payload = serde_json::to_vec(&set_paused(message_id)).unwrap();
+                let consensus = sign(&sks, &signers, &payload);
+                let encoding: ConsensusEncoding = match compact {
+                    true => CompactConsensus::from(&consensus).into(),
+                    false => consensus.into(),
+                };
+                let r = relay(&mut deps, encoding.clone(), &payload);
+                if signed >= POWER_THRESHOLD {
+                    prop_assert_eq!(r, Ok(()));
+                    // Accepted payloads can not be replayed, in either encoding.
+                    prop_assert_eq!(
+                        relay(&mut deps, encoding, &payload),
+                        Err(ContractError::MessageIdUsed {
+                            message_id: Uint256::from(message_id)
+                        })
+                    );
+                } else {
+                    prop_assert!(
+                        matches!(r, Err(ContractError::InsufficientPower { .. })),
+                        "{:?}",
+                        r
+                    );
+                }
+                Ok(())
+            },
+        )
+        .unwrap();
+}
+
+#[test]
+fn forged_signatures_are_rejected() {
+    runner(2, 64)
+        .run(
+            &(signed_valsets(), any::<prop::sample::Index>(), any::<u64>()),
+            |((sks, weights, _), forged, message_id)| {
+                let powers = normalize(&weights);
+                let Ok(mut deps) = setup(&sks, powers.clone()) else {
+                    return Ok(());
+                };
+                let payload = serde_json::to_vec(&set_paused(message_id)).unwrap();
+                // Every validator signs, but one of them signs another payload.
+                let mut consensus = sign(&sks, &vec![true; sks.len()], &payload);
+                let forged = forged.index(sks.len());
+                let other = serde_json::to_vec(&set_paused(message_id.wrapping_add(1))).unwrap();
+                consensus.signatures[forged] = sign(&sks, &vec![true; sks.len()], &other)
+                    .signatures
+                    .swap_remove(forged);
+                // Verification stops once the threshold is reached, so only a forgery among the
+                // validators needed to reach it is noticed.
+                let preceding: u64 = powers[..forged].iter().map(|&power| power as u64).sum();
+                let r = relay(&mut deps, consensus.into(), &payload);
+                if preceding < POWER_THRESHOLD {
+                    prop_assert_eq!(r, Err(ContractError::InvalidSignature { index: forged }));
+                } else {
+                    prop_assert_eq!(r, Ok(()));
+                }
+                Ok(())
+            },
+        )
+        .unwrap();
+}
+
+/// Single byte edits, insertions and truncations of `bytes`.
+fn mutations(bytes: Vec<u8>) -> impl Strategy<Value = Vec<u8>> {
+    let len = bytes.len();
+    prop::collection::vec((0..len, any::<u8>(), 0u8..3), 0..8).prop_map(move |edits| {
+        let mut bytes = bytes.clone();
+        for (index, byte, kind) in edits {
+            let index = index.min(bytes.len());
+            match kind {
+                0 if index < bytes.len() => bytes[index] = byte,
+                1 => bytes.insert(index, byte),
+                _ => bytes.truncate(index),
+            }
+        }
+        bytes
+    })
+}
+
+/// Parsing and executing mangled messages fails cleanly, even when validators signed the
+/// mangled payload.
+#[test]
+fn fuzz_message_parsing() {
+    let mut seed_runner = runner(3, 1);
+    let sks = secret_keys(4).new_tree(&mut seed_runner).unwrap().current();
+    let payload = serde_json::to_vec(&set_paused(1)).unwrap();
+    let consensus = sign(&sks, &[true; 4], &payload);
+    let msg = serde_json::to_vec(&ExecuteMsg::Consensus {
+        consensus: CompactConsensus::from(&consensus).into(),
+        payload: Binary(payload.clone()),
+    })
+    .unwrap();
+
+    runner(4, 512)
+        .run(&mutations(msg), |msg| {
+            if let Ok(msg) = from_json::<ExecuteMsg>(&msg) {
+                let mut deps = setup(&sks, vec![1 << 30; 4]).unwrap();
+                let _ = execute(
+                    deps.as_mut(),
+                    mock_env(),
+                    mock_info("relayer0000", &[]),
+                    msg,
+                );
+            }
+            Ok(())
+        })
+        .unwrap();
+    runner(5, 512)
+        .run(&mutations(payload.clone()), |payload| {
+            let mut deps = setup(&sks, vec![1 << 30; 4]).unwrap();
+            let consensus = sign(&sks, &[true; 4], &payload);
+            let r = relay(&mut deps, consensus.into(), &payload);
+            if serde_json::from_slice::<ExecutePayload>(&payload).is_err() {
+                prop_assert!(
+                    matches!(r, Err(ContractError::InvalidPayload { .. })),
+                    "{:?}",
+                    r
+                );
+            }
+            Ok(())
+        })
+        .unwrap();
+    runner(6, 512)
+        .run(
+            &(
+                prop::collection::vec(any::<u8>(), 0..3),
+                prop::collection::vec(any::<u8>(), 0..400),
+            ),
+            |(signer_bitmap, signatures)| {
+                let mut deps = setup(&sks, vec![1 << 30; 4]).unwrap();
+                let consensus = CompactConsensus {
+                    signer_bitmap: Binary(signer_bitmap),
+                    signatures: Binary(signatures),
+                };
+                prop_assert!(relay(&mut deps, consensus.into(), &payload).is_err());
+                Ok(())
+            },
+        )
+        .unwrap();
+}
+
+#[test]
+fn replays_are_always_rejected() {
+    let namespace = Namespace::instantiated(Addr::unchecked("contract0000"));
+    runner(7, 256)
+        .run(
+            &prop::collection::vec((0u8..16, 1u64..200, 0u8..3, 0u64..50), 1..200),
+            |calls| {
+                let mut deps = mock_dependencies();
+                let mut block = mock_env().block;
+                let mut executed = HashSet::new();
+                let mut used_ids = HashSet::new();
+                for (id, valid_for, bounds, wait) in calls {
+                    let time = block.time.seconds() + valid_for;
+                    let height = block.height + valid_for;
+                    let deadline = match bounds {
+                        0 => Deadline::Time(time),
+                        1 => Deadline::Bounds {
+                            time: None,
+                            height: Some(height),
+                        },
+                        _ => Deadline::Bounds {
+                            time: Some(time),
+                            height: Some(height),
+                        },
+                    };
+                    // Replay every payload executed so far, none may succeed again.
+                    for &(id, deadline) in &executed {
+                        let r = use_message_id(
+                            &mut deps.storage,
+                            &namespace,
+                            &block,
+                            Uint256::from(id),
+                            deadline,
+                            None,
+                        );
+                        prop_assert!(r.is_err());
+                    }
+                    let r = use_message_id(
+                        &mut deps.storage,
+                        &namespace,
+                        &block,
+                        Uint256::from(id),
+                        deadline,
+                        None,
+                    );
+                    if !used_ids.contains(&id) {
+                        prop_assert!(r.is_ok());
+                    }
+                    if r.is_ok() {
+                        executed.insert((id, deadline));
+                        used_ids.insert(id);
+                    }
+                    block.time = block.time.plus_seconds(wait);
+                    block.height += wait;
+                }
+                Ok(())
+            },
+        )
+        .unwrap();
+}
@@ -0,0 +1,352 @@
+//! Replay protection for consensus payloads.
+//!
+//! Every payload carrying a message id also carries a deadline, and can only execute before it.
+//! A used message id therefore only needs to be remembered until its deadline has passed: from
+//! then on a replay of the payload is rejected by the deadline check instead. Expired ids are
+//! pruned a few at a time as new ids are used, and deadlines are capped at
+//! `MAX_DEADLINE_HORIZON` seconds or `MAX_HEIGHT_HORIZON` blocks so that storage stays bounded
+//! by the number of payloads executed in that window.
+//!
+//! Message ids are used up per namespace, so that the Paloma instances compass serves can not
+//! exhaust each other's ids.
+//!
+//! Payloads addressed to an ordered target instead carry the next nonce of the target as their
+//! message id, so that they execute in the order they were signed. Every target counts nonces
+//! from zero, so used nonces are remembered per target until their deadline, like message ids.
+//! A payload does not execute again after its target switches nonce mode: until it expires, an
+//! ordered payload is rejected as an unordered message id of its target, and an unordered one as
+//! a nonce. The nonce of an ordered target is therefore only accepted once the unordered payloads
+//! that used it as message id for the same target have expired.
+//!
+//! Unversioned deployments kept every used message id forever, without its deadline. These ids
+//! stay where they are and are still checked for the namespace compass was instantiated with,
+//! since moving them could take more gas than a migration has. As their target is unknown, they
+//! are never accepted as nonces either.
+//!
+//! A deadline may bound the block time, the block height or both, in which case the payload
+//! expires with whichever is reached first. Ids are indexed for pruning by their time bound if
+//! they have one, and by their height bound otherwise.
+
+use crate::error::ContractError;
+use crate::msg::Deadline;
+use crate::state::{MessageIdRecord, Namespace, LEGACY_MESSAGE_ID_USED, ORDERED_TARGETS};
+use cosmwasm_std::ensure;
+use cosmwasm_std::{Addr, BlockInfo, Order, StdError, StdResult, Storage, Uint256};
+use cw_storage_plus::{Bound, Map, PrefixBound};
+
+/// Payloads may not be valid for longer than 30 days.
+pub(crate) const MAX_DEADLINE_HORIZON: u64 = 30 * 24 * 60 * 60;
+
+/// Payloads may not be valid for more blocks than there are seconds in `MAX_DEADLINE_HORIZON`.
+pub(crate) const MAX_HEIGHT_HORIZON: u64 = MAX_DEADLINE_HORIZON;
+
+/// Expired message ids removed per used message id.
+const PRUNE_LIMIT: usize = 10;
+
+/// Deadline reported for ids used by unversioned deployments, which are never pruned.
+const NO_DEADLINE: Deadline = Deadline::Time(u64::MAX);
+
+/// Fails if a payload valid until `deadline` has expired in `block`, or if the deadline is
+/// further away than the horizon.
+pub(crate) fn check_deadline(block: &BlockInfo, deadline: Deadline) -> Result<(), ContractError> {
+    let (time, height) = deadline.bounds();
+    ensure!(
+        time.is_some() || height.is_some(),
+        ContractError::EmptyDeadline
+    );
+    if let Some(deadline) = time {
+        let now = block.time.seconds();
+        ensure!(
+            now < deadline,
+            ContractError::Timeout {
+                deadline,
+                block_time: now,
+            }
+        );
+        ensure!(
+            deadline - now <= MAX_DEADLINE_HORIZON,
+            ContractError::DeadlineTooFar {
+                deadline,
+                block_time: now,
+                max_horizon: MAX_DEADLINE_HORIZON,
+            }
+        );
+    }
+    if let Some(deadline_height) = height {
+        ensure!(
+            block.height < deadline_height,
+            ContractError::HeightTimeout {
+                deadline_height,
+                block_height: block.height,
+            }
+        );
+        ensure!(
+            deadline_height - block.height <= MAX_HEIGHT_HORIZON,
+            ContractError::HeightDeadlineTooFar {
+                deadline_height,
+                block_height: block.height,
+                max_horizon: MAX_HEIGHT_HORIZON,
+            }
+        );
+    }
+    Ok(())
+}
+
+/// Marks `message_id` as used in `namespace` by a payload valid until `deadline` and addressed to
+/// `target`, failing if the payload has expired or the id was already used. For ordered targets,
+/// the id must be the next nonce instead.
+pub(crate) fn use_message_id(
+    storage: &mut dyn Storage,
+    namespace: &Namespace,
+    block: &BlockInfo,
+    message_id: Uint256,
+    deadline: Deadline,
+    target: Option<&Addr>,
+) -> Result<(), ContractError> {
+    check_deadline(block, deadline)?;
+    let record = MessageIdRecord {
+        deadline,
+        height: Some(block.height),
+        time: Some(block.time.seconds()),
+        target: target.cloned(),
+    };
+    if let Some(target) = target.filter(|target| ORDERED_TARGETS.has(storage, target)) {
+        // Legacy ids do not record their target, so they may have been used for this one.
+        let used_unordered = legacy_message_id_used(storage, namespace, message_id)
+            || namespace
+                .message_id_used()
+                .may_load(storage, &message_id.to_be_bytes())?
+                .is_some_and(|used| {
+                    used.target.as_ref() == Some(target) && !expired(used.deadline, block)
+                });
+        ensure!(!used_unordered, ContractError::MessageIdUsed { message_id });
+        use_nonce(storage, namespace, target, message_id)?;
+        let used = namespace.nonce_used();
+        prune_used(
+            storage,
+            &used,
+            namespace.nonce_expiry(),
+            namespace.nonce_height_expiry(),
+            block,
+        )?;
+        save_used(
+            storage,
+            &used,
+            namespace.nonce_expiry(),
+            namespace.nonce_height_expiry(),
+            &nonce_key(target, message_id),
+            &record,
+        )?;
+        return Ok(());
+    }
+    let used = namespace.message_id_used();
+    prune_used(
+        storage,
+        &used,
+        namespace.message_id_expiry(),
+        namespace.message_id_height_expiry(),
+        block,
+    )?;
+    ensure!(
+        message_id_record(storage, namespace, message_id)?.is_none(),
+        ContractError::MessageIdUsed { message_id }
+    );
+    if let Some(target) = target {
+        let used_ordered = nonce_record(storage, namespace, target, message_id)?
+            .filter(|used| !expired(used.deadline, block));
+        ensure!(
+            used_ordered.is_none(),
+            ContractError::MessageIdUsed { message_id }
+        );
+    }
+    save_used(
+        storage,
+        &used,
+        namespace.message_id_expiry(),
+        namespace.message_id_height_expiry(),
+        &message_id.to_be_bytes(),
+        &record,
+    )?;
+    Ok(())
+}
+
+/// Whether a payload valid until `deadline` can no longer execute in `block`.
+fn expired(deadline: Deadline, block: &BlockInfo) -> bool {
+    let (time, height) = deadline.bounds();
+    time.is_some_and(|time| time <= block.time.seconds())
+        || height.is_some_and(|height| height <= block.height)
+}
+
+fn nonce_key(target: &Addr, nonce: Uint256) -> Vec<u8> {
+    [target.as_bytes(), &nonce.to_be_bytes()].concat()
+}
+
+/// The record of `nonce` if a payload addressed to `target` in `namespace` used it while ordered.
+pub(crate) fn nonce_record(
+    storage: &dyn Storage,
+    namespace: &Namespace,
+    target: &Addr,
+    nonce: Uint256,
+) -> StdResult<Option<MessageIdRecord>> {
+    namespace
+        .nonce_used()
+        .may_load(storage, &nonce_key(target, nonce))
+}
+
+/// Up to `limit` of the nonces after `start_after` that payloads addressed to `target` in
+/// `namespace` used while ordered, with their records.
+pub(crate) fn used_nonces(
+    storage: &dyn Storage,
+    namespace: &Namespace,
+    target: &Addr,
+    start_after: Option<Uint256>,
+    limit: usize,
+) -> StdResult<Vec<(Uint256, MessageIdRecord)>> {
+    let start = nonce_key(target, start_after.unwrap_or_default());
+    let end = nonce_key(target, Uint256::MAX);
+    let start = match start_after {
+        Some(_) => Bound::exclusive(&start[..]),
+        None => Bound::inclusive(&start[..]),
+    };
+    namespace
+        .nonce_used()
+        .range(
+            storage,
+            Some(start),
+            Some(Bound::inclusive(&end[..])),
+            Order::Ascending,
+        )
+        // Nonces of targets whose address starts with `target` sort between its own.
+        .filter(|entry| {
+            entry
+                .as_ref()
+                .map_or(true, |(key, _)| key.len() == end.len())
+        })
+        .take(limit)
+        .map(|entry| {
+            let (key, record) = entry?;
+            let nonce: [u8; 32] = key[target.as_bytes().len()..].try_into().map_err(|_| {
+                StdError::invalid_data_size(32, key.len() - target.as_bytes().len())
+            })?;
+            Ok((Uint256::from_be_bytes(nonce), record))
+        })
+        .collect()
+}
+
+/// The nonce the next payload addressed to `target` in `namespace` must carry while it is ordered.
+pub(crate) fn next_nonce(
+    storage: &dyn Storage,
+    namespace: &Namespace,
+    target: &Addr,
+) -> StdResult<Uint256> {
+    Ok(namespace
+        .next_nonce()
+        .may_load(storage, target)?
+        .unwrap_or_default())
+}
+
+fn use_nonce(
+    storage: &mut dyn Storage,
+    namespace: &Namespace,
+    target: &Addr,
+    nonce: Uint256,
+) -> Result<(), ContractError> {
+    let expected = next_nonce(storage, namespace, target)?;
+    ensure!(
+        nonce == expected,
+        ContractError::NonceOutOfOrder {
+            target: target.clone(),
+            expected,
+            actual: nonce,
+        }
+    );
+    let next = nonce.checked_add(Uint256::one()).map_err(StdError::from)?;
+    namespace.next_nonce().save(storage, target, &next)?;
+    Ok(())
+}
+
+/// Forgets expired keys of `used`, a few at a time.
+fn prune_used(
+    storage: &mut dyn Storage,
+    used: &Map<&[u8], MessageIdRecord>,
+    expiry: Map<(u64, &[u8]), ()>,
+    height_expiry: Map<(u64, &[u8]), ()>,
+    block: &BlockInfo,
+) -> StdResult<()> {
+    prune_message_ids(storage, used, expiry, block.time.seconds())?;
+    prune_message_ids(storage, used, height_expiry, block.height)
+}
+
+/// Remembers `key` as used by `record`, indexed by its time bound if it has one and by its
+/// height bound otherwise.
+fn save_used(
+    storage: &mut dyn Storage,
+    used: &Map<&[u8], MessageIdRecord>,
+    expiry: Map<(u64, &[u8]), ()>,
+    height_expiry: Map<(u64, &[u8]), ()>,
+    key: &[u8],
+    record: &MessageIdRecord,
+) -> StdResult<()> {
+    used.save(storage, key, record)?;
+    match record.deadline.bounds() {
+        (Some(time), _) => expiry.save(storage, (time, key), &()),
+        (None, Some(height)) => height_expiry.save(storage, (height, key), &()),
+        (None, None) => unreachable!("checked by check_deadline"),
+    }
+}
+
+/// Forgets up to `PRUNE_LIMIT` message ids in `index` whose deadline is at or before `now`.
+fn prune_message_ids(
+    storage: &mut dyn Storage,
+    used: &Map<&[u8], MessageIdRecord>,
+    index: Map<(u64, &[u8]), ()>,
+    now: u64,
+) -> StdResult<()> {
+    let expired = index
+        .prefix_range(
+            storage,
+            None,
+            Some(PrefixBound::inclusive(now)),
+            Order::Ascending,
+        )
+        .take(PRUNE_LIMIT)
+        .map(|entry| entry.map(|(key, ())| key))
+        .collect::<StdResult<Vec<_>>>()?;
+    for (deadline, message_id) in expired {
+        index.remove(storage, (deadline, &message_id));
+        used.remove(storage, &message_id);
+    }
+    Ok(())
+}
+
+/// How `message_id` was used in `namespace`, if it was.
+pub(crate) fn message_id_record(
+    storage: &dyn Storage,
+    namespace: &Namespace,
+    message_id: Uint256,
+) -> StdResult<Option<MessageIdRecord>> {
+    let message_id_bytes = message_id.to_be_bytes();
+    if let Some(record) = namespace
+        .message_id_used()
+        .may_load(storage, &message_id_bytes)?
+    {
+        return Ok(Some(record));
+    }
+    let legacy = legacy_message_id_used(storage, namespace, message_id);
+    Ok(legacy.then_some(MessageIdRecord {
+        deadline: NO_DEADLINE,
+        height: None,
+        time: None,
+        target: None,
+    }))
+}
+
+/// Whether `message_id` was used in `namespace` by an unversioned deployment.
+fn legacy_message_id_used(
+    storage: &dyn Storage,
+    namespace: &Namespace,
+    message_id: Uint256,
+) -> bool {
+    namespace.is_instantiated()
+        && LEGACY_MESSAGE_ID_USED.has(storage, message_id.to_be_bytes().to_vec())
+}
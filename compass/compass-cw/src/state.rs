@@ -1,10 +1,146 @@
-use crate::msg::{Valset, ValsetId};
-use cosmwasm_std::Addr;
+use crate::msg::{
+    Deadline, PauseState, TargetLimits, TargetUsage, Thresholds, Valset, ValsetId, ValsetLogEntry,
+};
+use cosmwasm_std::{Addr, Uint128, Uint256};
 use cw_storage_plus::{Item, Map};
+use serde::{Deserialize, Serialize};
 
-pub const VALSET: Item<Valset> = Item::new("valset");
-pub const VALSET_ID: Item<ValsetId> = Item::new("valset_id");
-
+/// The smart contract id compass was instantiated with.
 pub const SMART_CONTRACT_ID: Item<Addr> = Item::new("smart_contract_id");
+/// Smart contract ids of the namespaces created since instantiation.
+pub const NAMESPACES: Map<&Addr, ()> = Map::new("namespaces");
+
+pub const PAUSED: Item<PauseState> = Item::new("paused");
+
+pub const THRESHOLDS: Item<Thresholds> = Item::new("thresholds");
+
+/// Unversioned deployments kept every used message id forever. They are still checked, see
+/// `replay`.
+pub const LEGACY_MESSAGE_ID_USED: Map<Vec<u8>, ()> = Map::new("message_id_used");
+
+/// Origin asset id to the cw20 representing it, and back.
+pub const WRAPPED_TOKENS: Map<&str, Addr> = Map::new("wrapped_tokens");
+pub const WRAPPED_TOKEN_ORIGINS: Map<&Addr, String> = Map::new("wrapped_token_origins");
+/// Origin asset id of the wrapped token being instantiated, consumed in `reply`.
+pub const PENDING_WRAPPED_TOKEN: Item<String> = Item::new("pending_wrapped_token");
+
+/// Funds available to reward relayers, by denom.
+pub const FEE_POOL: Map<&str, Uint128> = Map::new("fee_pool");
+
+/// Whether logic calls are restricted to `ALLOWED_TARGETS`.
+pub const TARGET_ALLOWLIST_ENABLED: Item<bool> = Item::new("target_allowlist_enabled");
+pub const ALLOWED_TARGETS: Map<&Addr, TargetLimits> = Map::new("allowed_targets");
+pub const TARGET_USAGE: Map<&Addr, TargetUsage> = Map::new("target_usage");
+/// Targets whose message ids are nonces, see `NonceMode::Ordered`.
+pub const ORDERED_TARGETS: Map<&Addr, ()> = Map::new("ordered_targets");
+
+/// How a message id was used.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MessageIdRecord {
+    pub deadline: Deadline,
+    pub height: Option<u64>,
+    pub time: Option<u64>,
+    pub target: Option<Addr>,
+}
+
+/// The storage of one Paloma instance served by compass: its valset, the message ids it used and
+/// the nonces of ordered targets.
+/// The namespace compass was instantiated with keeps the original storage keys, so deployments
+/// predating namespaces need no migration. Keys of other namespaces end in their smart contract
+/// id.
+pub struct Namespace {
+    pub smart_contract_id: Addr,
+    instantiated: bool,
+    valset: String,
+    valset_id: String,
+    valset_log: String,
+    message_id_used: String,
+    message_id_expiry: String,
+    message_id_height_expiry: String,
+    next_nonce: String,
+    nonce_used: String,
+    nonce_expiry: String,
+    nonce_height_expiry: String,
+}
+
+impl Namespace {
+    /// The namespace of the smart contract id compass was instantiated with.
+    pub fn instantiated(smart_contract_id: Addr) -> Self {
+        Self::new(smart_contract_id, true, String::new())
+    }
+
+    pub fn created(smart_contract_id: Addr) -> Self {
+        let suffix = format!(":{smart_contract_id}");
+        Self::new(smart_contract_id, false, suffix)
+    }
+
+    fn new(smart_contract_id: Addr, instantiated: bool, suffix: String) -> Self {
+        let key = |name: &str| format!("{name}{suffix}");
+        Namespace {
+            valset: key("valset"),
+            valset_id: key("valset_id"),
+            valset_log: key("valset_log"),
+            message_id_used: key("message_id_record"),
+            message_id_expiry: key("message_id_expiry"),
+            message_id_height_expiry: key("message_id_height_expiry"),
+            next_nonce: key("next_nonce"),
+            nonce_used: key("nonce_used"),
+            nonce_expiry: key("nonce_expiry"),
+            nonce_height_expiry: key("nonce_height_expiry"),
+            smart_contract_id,
+            instantiated,
+        }
+    }
+
+    pub fn is_instantiated(&self) -> bool {
+        self.instantiated
+    }
+
+    pub fn valset(&self) -> Item<'_, Valset> {
+        Item::new(&self.valset)
+    }
+
+    pub fn valset_id(&self) -> Item<'_, ValsetId> {
+        Item::new(&self.valset_id)
+    }
+
+    /// Recent valsets keyed by their big endian id.
+    pub fn valset_log(&self) -> Map<'_, &[u8], ValsetLogEntry> {
+        Map::new(&self.valset_log)
+    }
+
+    /// Used message ids and the payload that used them.
+    pub fn message_id_used(&self) -> Map<'_, &[u8], MessageIdRecord> {
+        Map::new(&self.message_id_used)
+    }
+
+    /// Used message ids keyed by deadline, so that expired ones can be pruned.
+    pub fn message_id_expiry(&self) -> Map<'_, (u64, &[u8]), ()> {
+        Map::new(&self.message_id_expiry)
+    }
+
+    /// Used message ids with only a height deadline, keyed by it.
+    pub fn message_id_height_expiry(&self) -> Map<'_, (u64, &[u8]), ()> {
+        Map::new(&self.message_id_height_expiry)
+    }
+
+    /// Nonce expected next by each target that was called in ordered mode.
+    pub fn next_nonce(&self) -> Map<'_, &Addr, Uint256> {
+        Map::new(&self.next_nonce)
+    }
+
+    /// Used nonces keyed by their target followed by the big endian nonce.
+    pub fn nonce_used(&self) -> Map<'_, &[u8], MessageIdRecord> {
+        Map::new(&self.nonce_used)
+    }
+
+    /// Used nonces keyed by deadline, so that expired ones can be pruned.
+    pub fn nonce_expiry(&self) -> Map<'_, (u64, &[u8]), ()> {
+        Map::new(&self.nonce_expiry)
+    }
 
-pub const MESSAGE_ID_USED: Map<Vec<u8>, ()> = Map::new("message_id_used");
+    /// Used nonces with only a height deadline, keyed by it.
+    pub fn nonce_height_expiry(&self) -> Map<'_, (u64, &[u8]), ()> {
+        Map::new(&self.nonce_height_expiry)
+    }
+}
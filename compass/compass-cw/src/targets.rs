@@ -0,0 +1,97 @@
+//! Allowlist and rate limits for logic call targets.
+//!
+//! Consensus may restrict logic calls to a set of allowed targets, and limit how often each of
+//! them is called and how much it is sent per window. Usage is counted in fixed windows that
+//! start with the first call after the previous window has ended, so only one record is kept
+//! per target.
+
+use crate::error::ContractError;
+use crate::msg::{LogicCallAction, TargetLimits, TargetUsage};
+use crate::state::{ALLOWED_TARGETS, TARGET_ALLOWLIST_ENABLED, TARGET_USAGE};
+use cosmwasm_std::{ensure, Addr, BlockInfo, StdResult, Storage};
+
+/// Records `action` against the usage of its target, failing if the target is not allowed or
+/// the action exceeds its limits.
+pub(crate) fn use_target(
+    storage: &mut dyn Storage,
+    block: &BlockInfo,
+    action: &LogicCallAction,
+) -> Result<(), ContractError> {
+    let Some(target) = action.target() else {
+        ensure!(
+            !TARGET_ALLOWLIST_ENABLED.load(storage)?,
+            ContractError::UntargetedAction {
+                action: action.name().to_string()
+            }
+        );
+        return Ok(());
+    };
+    let Some(limits) = ALLOWED_TARGETS.may_load(storage, &target)? else {
+        ensure!(
+            !TARGET_ALLOWLIST_ENABLED.load(storage)?,
+            ContractError::TargetNotAllowed {
+                target: target.clone()
+            }
+        );
+        return Ok(());
+    };
+    if limits.max_calls.is_none() && limits.max_funds.is_none() {
+        return Ok(());
+    }
+    let mut usage = current_usage(storage, block, &target, &limits)?;
+    usage.calls += 1;
+    if let Some(max_calls) = limits.max_calls {
+        ensure!(
+            usage.calls <= max_calls,
+            ContractError::CallLimitExceeded {
+                target: target.clone(),
+                max_calls,
+                window: limits.window,
+            }
+        );
+    }
+    for coin in action.funds() {
+        match usage.funds.iter_mut().find(|used| used.denom == coin.denom) {
+            Some(used) => used.amount += coin.amount,
+            None => usage.funds.push(coin),
+        }
+    }
+    if let Some(max_funds) = &limits.max_funds {
+        for used in &usage.funds {
+            let limit = max_funds
+                .iter()
+                .find(|max| max.denom == used.denom)
+                .map(|max| max.amount)
+                .unwrap_or_default();
+            ensure!(
+                used.amount <= limit,
+                ContractError::FundsLimitExceeded {
+                    target: target.clone(),
+                    denom: used.denom.clone(),
+                    limit,
+                    used: used.amount,
+                }
+            );
+        }
+    }
+    TARGET_USAGE.save(storage, &target, &usage)?;
+    Ok(())
+}
+
+/// Usage of `target` in the window that a call made in `block` falls into.
+pub(crate) fn current_usage(
+    storage: &dyn Storage,
+    block: &BlockInfo,
+    target: &Addr,
+    limits: &TargetLimits,
+) -> StdResult<TargetUsage> {
+    let now = block.time.seconds();
+    Ok(match TARGET_USAGE.may_load(storage, target)? {
+        Some(usage) if now < usage.window_start.saturating_add(limits.window) => usage,
+        _ => TargetUsage {
+            window_start: now,
+            calls: 0,
+            funds: vec![],
+        },
+    })
+}
@@ -1,14 +1,33 @@
-use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-use cosmwasm_std::{Addr, Binary, Uint256};
+use assert_matches::assert_matches;
+use cosmwasm_std::testing::{
+    mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MOCK_CONTRACT_ADDR,
+};
+use cosmwasm_std::{
+    coin, coins, from_json, to_json_binary, Addr, BankMsg, Binary, CosmosMsg, Env, Event, IbcMsg,
+    IbcTimeout, MemoryStorage, OwnedDeps, Reply, ReplyOn, Response, StakingMsg, SubMsgResponse,
+    SubMsgResult, Uint128, Uint256, WasmMsg,
+};
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg};
 use eyre::Result;
-use secp256k1::hashes::sha256;
 use secp256k1::rand::rngs::OsRng;
 use secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
 
-use crate::contract::{execute, instantiate};
+use crate::contract::{execute, instantiate, migrate, query, reply};
+use crate::error::{ContractError, ValsetError};
 use crate::msg::{
-    Consensus, ExecuteMsg, ExecutePayload, InstantiateMsg, LogicCallArgs, Valset, ValsetId,
+    AllowedTarget, BatchedLogicCall, CompactConsensus, ConfigResponse, Consensus,
+    ConsensusEncoding, Cw20HookMsg, Deadline, ExclusiveRelayer, ExecuteMsg, ExecutePayload,
+    Feature, InstantiateMsg, KeyType, LogicCallAction, LogicCallArgs, MigrateMsg, NextNonce,
+    NonceMode, PauseState, QueryMsg, RelayerReward, TargetAllowlistResponse, TargetLimits,
+    TargetUsage, Thresholds, UsedMessageId, UserMsg, Valset, ValsetId, ValsetLogEntry,
+    WrappedToken,
 };
+use crate::replay::{use_message_id, MAX_DEADLINE_HORIZON, MAX_HEIGHT_HORIZON};
+use crate::state::Namespace;
+
+fn instantiated() -> Namespace {
+    Namespace::instantiated(Addr::unchecked("contract0000"))
+}
 
 fn keys(n: u64) -> (Vec<SecretKey>, Vec<Binary>, Vec<u32>) {
     let secp = Secp256k1::new();
@@ -20,25 +39,38 @@ fn keys(n: u64) -> (Vec<SecretKey>, Vec<Binary>, Vec<u32>) {
             .iter()
             .map(|pk| Binary(pk.serialize().to_vec()))
             .collect(),
-        (1..n).map(|_| ((1 << 32) / n) as u32).collect(),
+        (0..n).map(|_| ((1 << 32) / n) as u32).collect(),
     )
 }
 
 fn execute_msg(sks: &[SecretKey], msg: &ExecutePayload) -> Result<ExecuteMsg> {
+    execute_msg_signed_by(sks, sks.len(), msg)
+}
+
+/// Only the first `signers` validators sign.
+fn execute_msg_signed_by(
+    sks: &[SecretKey],
+    signers: usize,
+    msg: &ExecutePayload,
+) -> Result<ExecuteMsg> {
     let secp = Secp256k1::new();
     let msg = serde_json::to_vec(&msg)?;
-    let hash = Message::from_hashed_data::<sha256::Hash>(&msg);
-    Ok(ExecuteMsg {
+    let hash = Message::from_slice(&crate::msg::payload_digest(&msg))?;
+    Ok(ExecuteMsg::Consensus {
         consensus: Consensus {
             signatures: sks
                 .iter()
-                .map(|sk| {
-                    Some(crate::msg::Signature(
-                        secp.sign_ecdsa(&hash, sk).serialize_compact().to_vec(),
-                    ))
+                .enumerate()
+                .map(|(i, sk)| {
+                    (i < signers).then(|| {
+                        crate::msg::Signature(
+                            secp.sign_ecdsa(&hash, sk).serialize_compact().to_vec(),
+                        )
+                    })
                 })
                 .collect(),
-        },
+        }
+        .into(),
         payload: Binary(msg),
     })
 }
@@ -62,6 +94,7 @@ fn basic_workflow() -> Result<()> {
                 valset_id: ValsetId(Uint256::zero()),
                 validators,
                 powers,
+                key_types: vec![],
             },
         },
     )?;
@@ -78,8 +111,10 @@ fn basic_workflow() -> Result<()> {
                     valset_id: ValsetId(Uint256::from(1u8)),
                     validators,
                     powers,
+                    key_types: vec![],
                 },
                 smart_contract_id: smart_contract_id.clone(),
+                deadline: None,
             },
         )
         .unwrap(),
@@ -91,10 +126,13 @@ fn basic_workflow() -> Result<()> {
         logic_call_args: LogicCallArgs {
             contract_address: Addr::unchecked("addr109"),
             payload: "".to_string(),
+            payload_binary: None,
+            funds: vec![],
         },
         message_id: Uint256::from(42u8),
         smart_contract_id: smart_contract_id.clone(),
-        deadline: mock_env().block.time.seconds() - 1,
+        deadline: (mock_env().block.time.seconds() - 1).into(),
+        relayer_reward: None,
     };
     let r = execute(
         deps.as_mut(),
@@ -102,24 +140,33 @@ fn basic_workflow() -> Result<()> {
         info.clone(),
         execute_msg(&sks, &payload)?,
     );
-    assert_eq!(&r.err().unwrap().to_string(), "Timeout");
+    assert_matches!(r.unwrap_err(), ContractError::Timeout { .. });
 
     let payload = ExecutePayload::SubmitLogicCall {
         logic_call_args: LogicCallArgs {
             contract_address: Addr::unchecked("addr109"),
             payload: "".to_string(),
+            payload_binary: None,
+            funds: vec![],
         },
         message_id: Uint256::from(42u8),
         smart_contract_id,
-        deadline: mock_env().block.time.seconds() + 1,
+        deadline: (mock_env().block.time.seconds() + 1).into(),
+        relayer_reward: None,
     };
     let r = execute(
         deps.as_mut(),
         mock_env(),
         info.clone(),
-        execute_msg(&sks[..2], &payload)?,
+        execute_msg_signed_by(&sks, 2, &payload)?,
+    );
+    assert_eq!(
+        r.unwrap_err(),
+        ContractError::InsufficientPower {
+            power: 2 * ((1 << 32) / 8),
+            threshold: 2_863_311_530,
+        }
     );
-    assert_eq!(&r.err().unwrap().to_string(), "Insufficient Power");
 
     let r = execute(
         deps.as_mut(),
@@ -135,7 +182,2644 @@ fn basic_workflow() -> Result<()> {
         info,
         execute_msg(&sks, &payload)?,
     );
-    assert_eq!(r.err().unwrap().to_string(), "Used Message_ID");
+    assert_eq!(
+        r.unwrap_err(),
+        ContractError::MessageIdUsed {
+            message_id: Uint256::from(42u8),
+        }
+    );
+
+    Ok(())
+}
+
+type TestDeps = OwnedDeps<MemoryStorage, MockApi, MockQuerier>;
+
+fn setup() -> Result<(TestDeps, Vec<SecretKey>)> {
+    let mut deps = mock_dependencies();
+    let (sks, validators, powers) = keys(8);
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("admin0000", &[]),
+        InstantiateMsg {
+            smart_contract_id: Addr::unchecked("contract0000"),
+            valset: Valset {
+                valset_id: ValsetId(Uint256::zero()),
+                validators,
+                powers,
+                key_types: vec![],
+            },
+        },
+    )?;
+    Ok((deps, sks))
+}
+
+/// Relays `payload` signed by every validator.
+fn relay(
+    deps: &mut TestDeps,
+    sks: &[SecretKey],
+    payload: &ExecutePayload,
+) -> Result<Response, ContractError> {
+    relay_at(deps, mock_env(), sks, payload)
+}
+
+fn relay_at(
+    deps: &mut TestDeps,
+    env: Env,
+    sks: &[SecretKey],
+    payload: &ExecutePayload,
+) -> Result<Response, ContractError> {
+    relay_msg(deps, env, execute_msg(sks, payload).unwrap())
+}
+
+/// Relays `payload` signed by the first `signers` validators only.
+fn relay_signed_by(
+    deps: &mut TestDeps,
+    sks: &[SecretKey],
+    signers: usize,
+    payload: &ExecutePayload,
+) -> Result<Response, ContractError> {
+    relay_msg(
+        deps,
+        mock_env(),
+        execute_msg_signed_by(sks, signers, payload).unwrap(),
+    )
+}
+
+fn relay_msg(deps: &mut TestDeps, env: Env, msg: ExecuteMsg) -> Result<Response, ContractError> {
+    execute(deps.as_mut(), env, mock_info("relayer0000", &[]), msg)
+}
+
+#[test]
+fn wrapped_tokens() -> Result<()> {
+    let (mut deps, sks) = setup()?;
+    let smart_contract_id = Addr::unchecked("contract0000");
+    let relayer = mock_info("relayer0000", &[]);
+
+    let deploy_as =
+        |origin_asset_id: &str, message_id: u8, deadline: u64| ExecutePayload::DeployWrappedToken {
+            origin_asset_id: origin_asset_id.to_string(),
+            code_id: 7,
+            name: "Wrapped Dead".to_string(),
+            symbol: "WDEAD".to_string(),
+            decimals: 18,
+            message_id: Uint256::from(message_id),
+            smart_contract_id: smart_contract_id.clone(),
+            deadline: deadline.into(),
+        };
+    let deadline = mock_env().block.time.seconds() + 1;
+    let deploy = deploy_as("eth-main:0xdead", 10, deadline);
+
+    // Deployments expire, and are halted with the bridge.
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        relayer.clone(),
+        execute_msg(
+            &sks,
+            &deploy_as("eth-main:0xdead", 10, mock_env().block.time.seconds()),
+        )?,
+    );
+    assert_matches!(r.unwrap_err(), ContractError::Timeout { .. });
+    crate::state::PAUSED.save(
+        &mut deps.storage,
+        &PauseState {
+            logic_calls: false,
+            bridge: true,
+        },
+    )?;
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        relayer.clone(),
+        execute_msg(&sks, &deploy)?,
+    );
+    assert_eq!(r.unwrap_err(), ContractError::BridgePaused);
+    crate::state::PAUSED.save(&mut deps.storage, &PauseState::default())?;
+
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        relayer.clone(),
+        execute_msg(&sks, &deploy)?,
+    )?;
+    assert_eq!(r.messages[0].reply_on, ReplyOn::Success);
+    match &r.messages[0].msg {
+        CosmosMsg::Wasm(WasmMsg::Instantiate {
+            admin,
+            code_id,
+            msg,
+            ..
+        }) => {
+            assert_eq!(admin.as_deref(), Some(mock_env().contract.address.as_str()));
+            assert_eq!(*code_id, 7);
+            let msg: cw20_base::msg::InstantiateMsg = from_json(msg)?;
+            assert_eq!(
+                msg.mint.unwrap().minter,
+                mock_env().contract.address.as_str()
+            );
+        }
+        _ => panic!("Expected an instantiate message."),
+    }
+    let token = Addr::unchecked("token0000");
+    reply(
+        deps.as_mut(),
+        mock_env(),
+        Reply {
+            id: r.messages[0].id,
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: vec![Event::new("instantiate").add_attribute("_contract_address", &token)],
+                data: None,
+            }),
+        },
+    )?;
+    let tokens: Vec<WrappedToken> = from_json(&query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::WrappedTokens {
+            start_after: None,
+            limit: None,
+        },
+    )?)?;
+    assert_eq!(
+        tokens,
+        vec![WrappedToken {
+            origin_asset_id: "eth-main:0xdead".to_string(),
+            address: token.clone(),
+        }]
+    );
+
+    // The same origin asset can only be wrapped once.
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        relayer.clone(),
+        execute_msg(&sks, &deploy)?,
+    );
+    assert_eq!(
+        r.unwrap_err(),
+        ContractError::WrappedTokenExists {
+            origin_asset_id: "eth-main:0xdead".to_string(),
+        }
+    );
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        relayer.clone(),
+        execute_msg(&sks, &deploy_as("eth-main:0xbeef", 10, deadline))?,
+    );
+    assert_eq!(
+        r.unwrap_err(),
+        ContractError::MessageIdUsed {
+            message_id: Uint256::from(10u8),
+        }
+    );
+
+    let transfer = ExecutePayload::TransferWrappedToken {
+        origin_asset_id: "eth-main:0xdead".to_string(),
+        recipient: Addr::unchecked("user0000"),
+        amount: Uint128::from(100u8),
+        message_id: Uint256::from(1u8),
+        smart_contract_id,
+        deadline: (mock_env().block.time.seconds() + 1).into(),
+        relayer_reward: None,
+    };
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        relayer.clone(),
+        execute_msg(&sks, &transfer)?,
+    )?;
+    assert_eq!(
+        r.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: token.to_string(),
+            msg: to_json_binary(&Cw20ExecuteMsg::Mint {
+                recipient: "user0000".to_string(),
+                amount: Uint128::from(100u8),
+            })?,
+            funds: vec![],
+        })
+    );
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        relayer,
+        execute_msg(&sks, &transfer)?,
+    );
+    assert_matches!(r.unwrap_err(), ContractError::MessageIdUsed { .. });
+
+    let bridge_out = ExecuteMsg::User(UserMsg::Receive(Cw20ReceiveMsg {
+        sender: "user0000".to_string(),
+        amount: Uint128::from(40u8),
+        msg: to_json_binary(&Cw20HookMsg::BridgeOut {
+            recipient: "0xbeef".to_string(),
+        })?,
+    }));
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("token0001", &[]),
+        bridge_out.clone(),
+    );
+    assert_eq!(
+        r.unwrap_err(),
+        ContractError::NotWrappedToken {
+            address: Addr::unchecked("token0001"),
+        }
+    );
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(token.as_str(), &[]),
+        bridge_out,
+    )?;
+    assert_eq!(
+        r.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: token.to_string(),
+            msg: to_json_binary(&Cw20ExecuteMsg::Burn {
+                amount: Uint128::from(40u8),
+            })?,
+            funds: vec![],
+        })
+    );
+    assert_eq!(r.events[0].ty, "bridge_out");
+
+    // Logic calls can not mint around the bridge, nor hand minting off.
+    let mint = ExecutePayload::SubmitLogicCall {
+        logic_call_args: LogicCallArgs {
+            contract_address: token.clone(),
+            payload: String::from_utf8(
+                to_json_binary(&Cw20ExecuteMsg::Mint {
+                    recipient: "user0000".to_string(),
+                    amount: Uint128::from(100u8),
+                })?
+                .0,
+            )?,
+            payload_binary: None,
+            funds: vec![],
+        },
+        message_id: Uint256::from(2u8),
+        smart_contract_id: Addr::unchecked("contract0000"),
+        deadline: (mock_env().block.time.seconds() + 1).into(),
+        relayer_reward: None,
+    };
+    assert_eq!(
+        relay(&mut deps, &sks, &mint).unwrap_err(),
+        ContractError::WrappedTokenTarget { target: token }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn execute_msg_wire_format() -> Result<()> {
+    // Relayers keep sending the untagged consensus form.
+    let msg: ExecuteMsg =
+        cosmwasm_std::from_json(br#"{"consensus":{"signatures":[null,[1,2]]},"payload":"e30="}"#)?;
+    assert_eq!(
+        msg,
+        ExecuteMsg::Consensus {
+            consensus: Consensus {
+                signatures: vec![None, Some(crate::msg::Signature(vec![1, 2]))],
+            }
+            .into(),
+            payload: Binary(b"{}".to_vec()),
+        }
+    );
+    let msg: ExecuteMsg =
+        cosmwasm_std::from_json(br#"{"receive":{"sender":"user0000","amount":"1","msg":"e30="}}"#)?;
+    assert!(matches!(msg, ExecuteMsg::User(UserMsg::Receive(_))));
+    Ok(())
+}
+
+#[test]
+fn query_msg_wire_format() -> Result<()> {
+    // Pigeon keeps sending the unit query forms.
+    let msg: QueryMsg = cosmwasm_std::from_json(br#""valset_id""#)?;
+    assert_eq!(msg, QueryMsg::ValsetId);
+    let msg: QueryMsg = cosmwasm_std::from_json(br#""smart_contract_id""#)?;
+    assert_eq!(msg, QueryMsg::SmartContractId);
+    let msg: QueryMsg = cosmwasm_std::from_json(
+        br#"{"namespace_valset_id":{"smart_contract_id":"contract0001"}}"#,
+    )?;
+    assert_eq!(
+        msg,
+        QueryMsg::NamespaceValsetId {
+            smart_contract_id: Addr::unchecked("contract0001"),
+        }
+    );
+
+    let (deps, _) = setup()?;
+    let valset_id: ValsetId = from_json(&query(
+        deps.as_ref(),
+        mock_env(),
+        cosmwasm_std::from_json(br#""valset_id""#)?,
+    )?)?;
+    assert_eq!(valset_id, ValsetId(Uint256::zero()));
+    Ok(())
+}
+
+#[test]
+fn relayer_rewards() -> Result<()> {
+    let (mut deps, sks) = setup()?;
+    let designated = Addr::unchecked("relayer0000");
+    let payload = |message_id: u8, exclusive_until: u64| ExecutePayload::SubmitLogicCall {
+        logic_call_args: LogicCallArgs {
+            contract_address: Addr::unchecked("addr109"),
+            payload: "".to_string(),
+            payload_binary: None,
+            funds: vec![],
+        },
+        message_id: Uint256::from(message_id),
+        smart_contract_id: Addr::unchecked("contract0000"),
+        deadline: (mock_env().block.time.seconds() + 1).into(),
+        relayer_reward: Some(RelayerReward {
+            reward: coin(10, "ugrain"),
+            exclusive_relayer: Some(ExclusiveRelayer {
+                address: designated.clone(),
+                until: exclusive_until,
+            }),
+        }),
+    };
+    let now = mock_env().block.time.seconds();
+
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(designated.as_str(), &[]),
+        execute_msg(&sks, &payload(1, now + 1))?,
+    );
+    assert_eq!(
+        r.unwrap_err(),
+        ContractError::InsufficientFeePool {
+            denom: "ugrain".to_string(),
+            available: Uint128::zero(),
+            required: Uint128::from(10u8),
+        }
+    );
+
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("funder0000", &coins(15, "ugrain")),
+        ExecuteMsg::User(UserMsg::TopUpFeePool {}),
+    )?;
+
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("relayer0001", &[]),
+        execute_msg(&sks, &payload(1, now + 1))?,
+    );
+    assert_eq!(
+        r.unwrap_err(),
+        ContractError::ReservedForRelayer {
+            relayer: designated.clone(),
+            until: now + 1,
+        }
+    );
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(designated.as_str(), &[]),
+        execute_msg(&sks, &payload(1, now + 1))?,
+    )?;
+    assert_eq!(
+        r.messages[1].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: designated.to_string(),
+            amount: coins(10, "ugrain"),
+        })
+    );
+
+    // Once exclusivity has lapsed anyone may relay, as long as the pool can pay.
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("relayer0001", &[]),
+        execute_msg(&sks, &payload(2, now))?,
+    );
+    assert_matches!(r.unwrap_err(), ContractError::InsufficientFeePool { .. });
+    let pool: Vec<cosmwasm_std::Coin> =
+        from_json(&query(deps.as_ref(), mock_env(), QueryMsg::FeePool)?)?;
+    assert_eq!(pool, coins(5, "ugrain"));
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("funder0000", &coins(5, "ugrain")),
+        ExecuteMsg::User(UserMsg::TopUpFeePool {}),
+    )?;
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("relayer0001", &[]),
+        execute_msg(&sks, &payload(2, now))?,
+    )?;
+    assert_eq!(
+        r.messages[1].msg,
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: "relayer0001".to_string(),
+            amount: coins(10, "ugrain"),
+        })
+    );
+
+    // A reward of nothing could not be sent.
+    let mut zero_reward = payload(3, now);
+    if let ExecutePayload::SubmitLogicCall { relayer_reward, .. } = &mut zero_reward {
+        *relayer_reward = Some(RelayerReward {
+            reward: coin(0, "ugrain"),
+            exclusive_relayer: None,
+        });
+    }
+    assert_eq!(
+        relay(&mut deps, &sks, &zero_reward).unwrap_err(),
+        ContractError::ZeroRelayerReward {
+            denom: "ugrain".to_string(),
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn pause() -> Result<()> {
+    let (mut deps, sks) = setup()?;
+    let smart_contract_id = Addr::unchecked("contract0000");
+    let relayer = mock_info("relayer0000", &[]);
+    let deadline = mock_env().block.time.seconds() + 1;
+    let set_paused = |message_id: u8, logic_calls: bool, bridge: bool| ExecutePayload::SetPaused {
+        paused: PauseState {
+            logic_calls,
+            bridge,
+        },
+        message_id: Uint256::from(message_id),
+        smart_contract_id: smart_contract_id.clone(),
+        deadline: deadline.into(),
+    };
+    let logic_call = ExecutePayload::SubmitLogicCall {
+        logic_call_args: LogicCallArgs {
+            contract_address: Addr::unchecked("addr109"),
+            payload: "".to_string(),
+            payload_binary: None,
+            funds: vec![],
+        },
+        message_id: Uint256::from(42u8),
+        smart_contract_id: smart_contract_id.clone(),
+        deadline: deadline.into(),
+        relayer_reward: None,
+    };
+
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        relayer.clone(),
+        execute_msg(&sks, &set_paused(1, true, true))?,
+    )?;
+    assert_eq!(r.events[0].ty, "paused");
+    let paused: PauseState = from_json(&query(deps.as_ref(), mock_env(), QueryMsg::Paused)?)?;
+    assert_eq!(
+        paused,
+        PauseState {
+            logic_calls: true,
+            bridge: true,
+        }
+    );
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        relayer.clone(),
+        execute_msg(&sks, &logic_call)?,
+    );
+    assert_eq!(r.unwrap_err(), ContractError::LogicCallsPaused);
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("token0000", &[]),
+        ExecuteMsg::User(UserMsg::Receive(Cw20ReceiveMsg {
+            sender: "user0000".to_string(),
+            amount: Uint128::from(1u8),
+            msg: to_json_binary(&Cw20HookMsg::BridgeOut {
+                recipient: "0xbeef".to_string(),
+            })?,
+        })),
+    );
+    assert_eq!(r.unwrap_err(), ContractError::BridgePaused);
+
+    // Valsets can still rotate while paused.
+    let (sks1, validators, powers) = keys(8);
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        relayer.clone(),
+        execute_msg(
+            &sks,
+            &ExecutePayload::UpdateValset {
+                valset: Valset {
+                    valset_id: ValsetId(Uint256::from(1u8)),
+                    validators,
+                    powers,
+                    key_types: vec![],
+                },
+                smart_contract_id: smart_contract_id.clone(),
+                deadline: None,
+            },
+        )?,
+    )?;
+    let sks = sks1;
+
+    // A pause payload cannot be replayed to undo a later unpause.
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        relayer.clone(),
+        execute_msg(&sks, &set_paused(2, false, false))?,
+    )?;
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        relayer.clone(),
+        execute_msg(&sks, &set_paused(1, true, true))?,
+    );
+    assert_eq!(
+        r.unwrap_err(),
+        ContractError::MessageIdUsed {
+            message_id: Uint256::from(1u8),
+        }
+    );
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        relayer,
+        execute_msg(&sks, &logic_call)?,
+    )?;
+    assert_eq!(r.messages.len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn self_migration() -> Result<()> {
+    let (mut deps, sks) = setup()?;
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("relayer0000", &[]),
+        execute_msg(
+            &sks,
+            &ExecutePayload::Migrate {
+                new_code_id: 12,
+                msg: to_json_binary(&MigrateMsg {})?,
+                message_id: Uint256::from(1u8),
+                smart_contract_id: Addr::unchecked("contract0000"),
+                deadline: (mock_env().block.time.seconds() + 1).into(),
+            },
+        )?,
+    )?;
+    assert_eq!(
+        r.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Migrate {
+            contract_addr: mock_env().contract.address.into_string(),
+            new_code_id: 12,
+            msg: to_json_binary(&MigrateMsg {})?,
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn migrate_versions() -> Result<()> {
+    let (mut deps, _) = setup()?;
+    let version = cw2::get_contract_version(&deps.storage)?;
+    assert_eq!(version.contract, "crates.io:compass-cw");
+
+    // Deployments predating cw2 are brought up to date.
+    cw2::CONTRACT.remove(&mut deps.storage);
+    crate::state::PAUSED.remove(&mut deps.storage);
+    instantiated()
+        .valset_log()
+        .remove(&mut deps.storage, &Uint256::zero().to_be_bytes());
+    let legacy_id = Uint256::from(7u8);
+    crate::state::LEGACY_MESSAGE_ID_USED.save(
+        &mut deps.storage,
+        legacy_id.to_be_bytes().to_vec(),
+        &(),
+    )?;
+    let r = migrate(deps.as_mut(), mock_env(), MigrateMsg {})?;
+    assert_eq!(r.attributes[0].value, "0.1.0");
+    assert_eq!(cw2::get_contract_version(&deps.storage)?, version);
+    let paused: PauseState = from_json(&query(deps.as_ref(), mock_env(), QueryMsg::Paused)?)?;
+    assert_eq!(paused, PauseState::default());
+    let log: Vec<ValsetLogEntry> = from_json(&query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::ValsetLog {
+            start_after: None,
+            limit: None,
+            smart_contract_id: None,
+        },
+    )?)?;
+    assert_eq!(log.len(), 1);
+    assert_eq!(log[0].height, mock_env().block.height);
+    let used: Option<UsedMessageId> = from_json(&query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::UsedMessageId {
+            message_id: legacy_id,
+            target: None,
+            smart_contract_id: None,
+        },
+    )?)?;
+    assert_eq!(
+        used,
+        Some(UsedMessageId {
+            message_id: legacy_id,
+            deadline: Deadline::Time(u64::MAX),
+            height: None,
+            time: None,
+            target: None,
+        })
+    );
+    // Legacy ids are left in place, and still rejected.
+    assert!(
+        crate::state::LEGACY_MESSAGE_ID_USED.has(&deps.storage, legacy_id.to_be_bytes().to_vec())
+    );
+    let r = use_message_id(
+        &mut deps.storage,
+        &instantiated(),
+        &mock_env().block,
+        legacy_id,
+        (mock_env().block.time.seconds() + 1).into(),
+        None,
+    );
+    assert_eq!(
+        r.unwrap_err(),
+        ContractError::MessageIdUsed {
+            message_id: legacy_id,
+        }
+    );
 
+    // Migrating to the same version is a no-op.
+    migrate(deps.as_mut(), mock_env(), MigrateMsg {})?;
+
+    cw2::set_contract_version(&mut deps.storage, "crates.io:compass-cw", "9.9.9")?;
+    let r = migrate(deps.as_mut(), mock_env(), MigrateMsg {});
+    assert_eq!(
+        r.unwrap_err(),
+        ContractError::MigrateUnknownVersion {
+            version: "9.9.9".to_string(),
+        }
+    );
+    cw2::set_contract_version(&mut deps.storage, "crates.io:cw20-base", "0.2.0")?;
+    let r = migrate(deps.as_mut(), mock_env(), MigrateMsg {});
+    assert_eq!(
+        r.unwrap_err(),
+        ContractError::MigrateWrongContract {
+            contract: "crates.io:cw20-base".to_string(),
+        }
+    );
+    Ok(())
+}
+
+#[test]
+fn expired_message_ids_are_pruned() -> Result<()> {
+    let mut deps = mock_dependencies();
+    let mut block = mock_env().block;
+    let now = block.time.seconds();
+    for id in 0..20u8 {
+        use_message_id(
+            &mut deps.storage,
+            &instantiated(),
+            &block,
+            Uint256::from(id),
+            (now + 10).into(),
+            None,
+        )?;
+    }
+    use_message_id(
+        &mut deps.storage,
+        &instantiated(),
+        &block,
+        Uint256::from(20u8),
+        (now + 100).into(),
+        None,
+    )?;
+    let r = use_message_id(
+        &mut deps.storage,
+        &instantiated(),
+        &block,
+        Uint256::from(21u8),
+        (now + MAX_DEADLINE_HORIZON + 1).into(),
+        None,
+    );
+    assert_matches!(r.unwrap_err(), ContractError::DeadlineTooFar { .. });
+
+    block.time = block.time.plus_seconds(10);
+    use_message_id(
+        &mut deps.storage,
+        &instantiated(),
+        &block,
+        Uint256::from(21u8),
+        (now + 100).into(),
+        None,
+    )?;
+    use_message_id(
+        &mut deps.storage,
+        &instantiated(),
+        &block,
+        Uint256::from(22u8),
+        (now + 100).into(),
+        None,
+    )?;
+    let stored: Vec<_> = instantiated()
+        .message_id_used()
+        .keys(&deps.storage, None, None, cosmwasm_std::Order::Ascending)
+        .collect::<cosmwasm_std::StdResult<_>>()?;
+    assert_eq!(stored.len(), 3);
+
+    // Replays of pruned ids are stopped by their deadline.
+    let r = use_message_id(
+        &mut deps.storage,
+        &instantiated(),
+        &block,
+        Uint256::from(0u8),
+        (now + 10).into(),
+        None,
+    );
+    assert_eq!(
+        r.unwrap_err(),
+        ContractError::Timeout {
+            deadline: now + 10,
+            block_time: now + 10,
+        }
+    );
+    Ok(())
+}
+
+#[test]
+fn valset_validation() -> Result<()> {
+    let (mut deps, sks) = setup()?;
+    let update = |validators: Vec<Binary>, powers: Vec<u32>| ExecutePayload::UpdateValset {
+        valset: Valset {
+            valset_id: ValsetId(Uint256::from(1u8)),
+            validators,
+            powers,
+            key_types: vec![],
+        },
+        smart_contract_id: Addr::unchecked("contract0000"),
+        deadline: None,
+    };
+    let reject = |deps: &mut OwnedDeps<_, _, _>, payload: ExecutePayload| -> Result<ValsetError> {
+        let r = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("relayer0000", &[]),
+            execute_msg(&sks, &payload)?,
+        );
+        match r.unwrap_err() {
+            ContractError::Valset(e) => Ok(e),
+            e => panic!("Unexpected error {e}"),
+        }
+    };
+    let (_, validators, powers) = keys(4);
+
+    assert_eq!(
+        reject(&mut deps, update(vec![], vec![]))?,
+        ValsetError::Empty
+    );
+    assert_eq!(
+        reject(&mut deps, update(validators.clone(), powers[1..].to_vec()))?,
+        ValsetError::LengthMismatch {
+            validators: 4,
+            powers: 3,
+        }
+    );
+    let mut bad_keys = validators.clone();
+    // The x coordinate is larger than the field modulus.
+    bad_keys[2] = Binary([vec![2], vec![0xff; 32]].concat());
+    assert_eq!(
+        reject(&mut deps, update(bad_keys, powers.clone()))?,
+        ValsetError::InvalidKey {
+            index: 2,
+            key_type: KeyType::Secp256k1,
+        }
+    );
+    let mut uncompressed = validators.clone();
+    uncompressed[1] = Binary(
+        PublicKey::from_slice(&validators[1])?
+            .serialize_uncompressed()
+            .to_vec(),
+    );
+    assert_eq!(
+        reject(&mut deps, update(uncompressed, powers.clone()))?,
+        ValsetError::InvalidKey {
+            index: 1,
+            key_type: KeyType::Secp256k1,
+        }
+    );
+    let mut ed25519 = update(validators.clone(), powers.clone());
+    if let ExecutePayload::UpdateValset { valset, .. } = &mut ed25519 {
+        valset.key_types = vec![KeyType::Ed25519; 3];
+    }
+    assert_eq!(
+        reject(&mut deps, ed25519.clone())?,
+        ValsetError::KeyTypeMismatch {
+            validators: 4,
+            key_types: 3,
+        }
+    );
+    if let ExecutePayload::UpdateValset { valset, .. } = &mut ed25519 {
+        valset.key_types = vec![KeyType::Secp256k1, KeyType::Ed25519];
+        valset.key_types.resize(4, KeyType::Secp256k1);
+    }
+    // Compressed secp256k1 keys are a byte longer than ed25519 keys.
+    assert_eq!(
+        reject(&mut deps, ed25519)?,
+        ValsetError::InvalidKey {
+            index: 1,
+            key_type: KeyType::Ed25519,
+        }
+    );
+    let mut duplicates = validators.clone();
+    duplicates[3] = duplicates[0].clone();
+    assert_eq!(
+        reject(&mut deps, update(duplicates, powers.clone()))?,
+        ValsetError::DuplicateKey { index: 3, first: 0 }
+    );
+    assert_eq!(
+        reject(&mut deps, update(validators.clone(), vec![u32::MAX; 4]))?,
+        ValsetError::PowerOverflow {
+            total: 4 * u32::MAX as u64,
+        }
+    );
+    assert_eq!(
+        reject(&mut deps, update(validators.clone(), vec![1 << 28; 4]))?,
+        ValsetError::InsufficientPower {
+            total: 1 << 30,
+            threshold: 2_863_311_530,
+        }
+    );
+
+    // Consensus must carry exactly one entry per validator.
+    let mut msg = execute_msg(&sks, &update(validators, powers))?;
+    if let ExecuteMsg::Consensus {
+        consensus: ConsensusEncoding::Full(consensus),
+        ..
+    } = &mut msg
+    {
+        consensus.signatures.push(None);
+    }
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("relayer0000", &[]),
+        msg,
+    );
+    assert_eq!(
+        r.unwrap_err(),
+        ContractError::Valset(ValsetError::SignatureCountMismatch {
+            signatures: 9,
+            validators: 8,
+        })
+    );
+
+    // Instantiation is held to the same rules.
+    let r = instantiate(
+        mock_dependencies().as_mut(),
+        mock_env(),
+        mock_info("admin0000", &[]),
+        InstantiateMsg {
+            smart_contract_id: Addr::unchecked("contract0000"),
+            valset: Valset {
+                valset_id: ValsetId(Uint256::zero()),
+                validators: vec![],
+                powers: vec![],
+                key_types: vec![],
+            },
+        },
+    );
+    assert_eq!(r.unwrap_err(), ContractError::Valset(ValsetError::Empty));
+    Ok(())
+}
+
+/// A validator key of either type.
+enum MixedKey {
+    Secp256k1(SecretKey),
+    Ed25519(Box<ed25519_zebra::SigningKey>),
+}
+
+impl MixedKey {
+    fn public_key(&self) -> Binary {
+        Binary(match self {
+            MixedKey::Secp256k1(sk) => sk.public_key(&Secp256k1::new()).serialize().to_vec(),
+            MixedKey::Ed25519(sk) => ed25519_zebra::VerificationKey::from(&**sk)
+                .as_ref()
+                .to_vec(),
+        })
+    }
+
+    fn sign(&self, payload: &[u8]) -> crate::msg::Signature {
+        let digest = crate::msg::payload_digest(payload);
+        crate::msg::Signature(match self {
+            MixedKey::Secp256k1(sk) => Secp256k1::new()
+                .sign_ecdsa(&Message::from_slice(&digest).unwrap(), sk)
+                .serialize_compact()
+                .to_vec(),
+            MixedKey::Ed25519(sk) => <[u8; 64]>::from(sk.sign(&digest)).to_vec(),
+        })
+    }
+}
+
+#[test]
+fn mixed_valsets() -> Result<()> {
+    let (mut deps, sks) = setup()?;
+    let (secp256k1_keys, _, _) = keys(2);
+    let ed25519_keys: Vec<_> = (0..2)
+        .map(|_| Box::new(ed25519_zebra::SigningKey::new(OsRng)))
+        .collect();
+    // The ed25519 validators come first so that both are checked in one batch.
+    let mixed: Vec<_> = ed25519_keys
+        .into_iter()
+        .map(MixedKey::Ed25519)
+        .chain(secp256k1_keys.into_iter().map(MixedKey::Secp256k1))
+        .collect();
+    let valset = Valset {
+        valset_id: ValsetId(Uint256::from(1u8)),
+        validators: mixed.iter().map(MixedKey::public_key).collect(),
+        powers: vec![1 << 30; 4],
+        key_types: vec![
+            KeyType::Ed25519,
+            KeyType::Ed25519,
+            KeyType::Secp256k1,
+            KeyType::Secp256k1,
+        ],
+    };
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("relayer0000", &[]),
+        execute_msg(
+            &sks,
+            &ExecutePayload::UpdateValset {
+                valset: valset.clone(),
+                smart_contract_id: Addr::unchecked("contract0000"),
+                deadline: None,
+            },
+        )?,
+    )?;
+    let log: Vec<ValsetLogEntry> = from_json(&query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::ValsetLog {
+            start_after: Some(ValsetId(Uint256::zero())),
+            limit: None,
+            smart_contract_id: None,
+        },
+    )?)?;
+    assert_eq!(log[0].hash, hex::encode(crate::msg::valset_hash(&valset)));
+    let secp256k1_only = Valset {
+        key_types: vec![],
+        ..valset.clone()
+    };
+    assert_ne!(
+        crate::msg::valset_hash(&secp256k1_only),
+        crate::msg::valset_hash(&valset)
+    );
+
+    let pause = |message_id: u8| {
+        serde_json::to_vec(&ExecutePayload::SetPaused {
+            paused: PauseState::default(),
+            message_id: Uint256::from(message_id),
+            smart_contract_id: Addr::unchecked("contract0000"),
+            deadline: Deadline::Time(mock_env().block.time.seconds() + 1),
+        })
+    };
+    let mut relay = |signatures: Vec<Option<crate::msg::Signature>>, payload: Vec<u8>, compact| {
+        let consensus = Consensus { signatures };
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("relayer0000", &[]),
+            ExecuteMsg::Consensus {
+                consensus: match compact {
+                    true => CompactConsensus::from(&consensus).into(),
+                    false => consensus.into(),
+                },
+                payload: Binary(payload),
+            },
+        )
+    };
+
+    // Three of the four validators reach the threshold, whichever their key types.
+    for (message_id, signers, compact) in [(1, [0, 1, 2], false), (2, [1, 2, 3], true)] {
+        let payload = pause(message_id)?;
+        let signatures = (0..4)
+            .map(|index| {
+                signers
+                    .contains(&index)
+                    .then(|| mixed[index].sign(&payload))
+            })
+            .collect();
+        relay(signatures, payload, compact)?;
+    }
+    let payload = pause(3)?;
+    assert_eq!(
+        relay(
+            vec![
+                Some(mixed[0].sign(&payload)),
+                Some(mixed[1].sign(&payload)),
+                None,
+                None
+            ],
+            payload,
+            false
+        ),
+        Err(ContractError::InsufficientPower {
+            power: 1 << 31,
+            threshold: 2_863_311_530,
+        })
+    );
+
+    // A forged ed25519 signature fails the batch and is then pinpointed.
+    let payload = pause(4)?;
+    let mut signatures: Vec<_> = mixed.iter().map(|key| Some(key.sign(&payload))).collect();
+    signatures[1] = Some(mixed[1].sign(&pause(5)?));
+    assert_eq!(
+        relay(signatures, payload, false),
+        Err(ContractError::InvalidSignature { index: 1 })
+    );
+    // Signatures of the wrong scheme are invalid.
+    let payload = pause(6)?;
+    let mut signatures: Vec<_> = mixed.iter().map(|key| Some(key.sign(&payload))).collect();
+    signatures[0] = Some(mixed[2].sign(&payload));
+    assert_eq!(
+        relay(signatures, payload, true),
+        Err(ContractError::InvalidSignature { index: 0 })
+    );
+    Ok(())
+}
+
+#[test]
+fn errors_serialize_to_stable_json() -> Result<()> {
+    assert_eq!(
+        serde_json::to_string(&ContractError::Timeout {
+            deadline: 10,
+            block_time: 11,
+        })?,
+        r#"{"timeout":{"deadline":10,"block_time":11}}"#
+    );
+    assert_eq!(
+        serde_json::to_string(&ContractError::Valset(ValsetError::InvalidKey {
+            index: 3,
+            key_type: KeyType::Ed25519,
+        }))?,
+        r#"{"valset":{"invalid_key":{"index":3,"key_type":"ed25519"}}}"#
+    );
+    assert_eq!(
+        serde_json::to_string(&ContractError::LogicCallsPaused)?,
+        r#""logic_calls_paused""#
+    );
+    assert_eq!(
+        serde_json::to_string(&ContractError::Std(cosmwasm_std::StdError::generic_err(
+            "oops"
+        )))?,
+        r#"{"std":"Generic error: oops"}"#
+    );
+    Ok(())
+}
+
+#[test]
+fn logic_call_batch() -> Result<()> {
+    let (mut deps, sks) = setup()?;
+    let batch = |message_ids: &[u8]| ExecutePayload::SubmitLogicCallBatch {
+        logic_calls: message_ids
+            .iter()
+            .map(|&id| BatchedLogicCall {
+                logic_call_args: LogicCallArgs {
+                    contract_address: Addr::unchecked(format!("addr{id:03}")),
+                    payload: format!("{{\"call\":{id}}}"),
+                    payload_binary: None,
+                    funds: vec![],
+                },
+                message_id: Uint256::from(id),
+            })
+            .collect(),
+        smart_contract_id: Addr::unchecked("contract0000"),
+        deadline: (mock_env().block.time.seconds() + 1).into(),
+        relayer_reward: None,
+    };
+
+    assert_eq!(
+        relay(&mut deps, &sks, &batch(&[])).unwrap_err(),
+        ContractError::EmptyBatch
+    );
+
+    let r = relay(&mut deps, &sks, &batch(&[3, 1, 2]))?;
+    let targets: Vec<_> = r
+        .messages
+        .iter()
+        .map(|msg| match &msg.msg {
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr, msg, ..
+            }) => (contract_addr.clone(), msg.clone()),
+            _ => panic!("Expected an execute message."),
+        })
+        .collect();
+    assert_eq!(
+        targets,
+        vec![
+            ("addr003".to_string(), Binary(br#"{"call":3}"#.to_vec())),
+            ("addr001".to_string(), Binary(br#"{"call":1}"#.to_vec())),
+            ("addr002".to_string(), Binary(br#"{"call":2}"#.to_vec())),
+        ]
+    );
+    let message_ids: Vec<_> = r
+        .events
+        .iter()
+        .filter(|event| event.ty == "logic_call")
+        .map(|event| event.attributes[0].value.clone())
+        .collect();
+    assert_eq!(message_ids, ["3", "1", "2"]);
+
+    // Any used id fails the whole batch.
+    assert_eq!(
+        relay(&mut deps, &sks, &batch(&[4, 2])).unwrap_err(),
+        ContractError::MessageIdUsed {
+            message_id: Uint256::from(2u8),
+        }
+    );
+    assert_eq!(
+        relay(&mut deps, &sks, &batch(&[5, 5])).unwrap_err(),
+        ContractError::MessageIdUsed {
+            message_id: Uint256::from(5u8),
+        }
+    );
+    Ok(())
+}
+
+#[test]
+fn target_allowlist() -> Result<()> {
+    let (mut deps, sks) = setup()?;
+    let smart_contract_id = Addr::unchecked("contract0000");
+    let deadline = mock_env().block.time.seconds() + 1;
+    let allowed = Addr::unchecked("addr100");
+    let other = Addr::unchecked("addr200");
+    let update = |message_id: u8, enabled, allow, remove| ExecutePayload::UpdateTargetAllowlist {
+        enabled,
+        allow,
+        remove,
+        message_id: Uint256::from(message_id),
+        smart_contract_id: smart_contract_id.clone(),
+        deadline: deadline.into(),
+    };
+    let logic_call = |message_id: u8, target: &Addr, funds| ExecutePayload::SubmitLogicCall {
+        logic_call_args: LogicCallArgs {
+            contract_address: target.clone(),
+            payload: "".to_string(),
+            payload_binary: None,
+            funds,
+        },
+        message_id: Uint256::from(message_id),
+        smart_contract_id: smart_contract_id.clone(),
+        deadline: deadline.into(),
+        relayer_reward: None,
+    };
+    let usage = |deps: &OwnedDeps<_, _, _>, env, target: &Addr| {
+        from_json::<Option<TargetUsage>>(
+            &query(
+                deps.as_ref(),
+                env,
+                QueryMsg::TargetUsage {
+                    target: target.clone(),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap()
+    };
+
+    // Compass holds 1000ugrain, 900ugrain of which are reserved for relayers.
+    deps.querier
+        .update_balance(MOCK_CONTRACT_ADDR, coins(1000, "ugrain"));
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("donor0000", &coins(900, "ugrain")),
+        ExecuteMsg::User(UserMsg::TopUpFeePool {}),
+    )?;
+
+    let limits = TargetLimits {
+        window: 60,
+        max_calls: Some(2),
+        max_funds: Some(coins(100, "ugrain")),
+    };
+    assert_eq!(
+        relay(
+            &mut deps,
+            &sks,
+            &update(
+                1,
+                Some(true),
+                vec![AllowedTarget {
+                    address: allowed.clone(),
+                    limits: TargetLimits {
+                        window: 0,
+                        ..limits.clone()
+                    },
+                }],
+                vec![],
+            )
+        )
+        .unwrap_err(),
+        ContractError::InvalidTargetLimits {
+            target: allowed.clone()
+        }
+    );
+    let r = relay(
+        &mut deps,
+        &sks,
+        &update(
+            1,
+            Some(true),
+            vec![AllowedTarget {
+                address: allowed.clone(),
+                limits: limits.clone(),
+            }],
+            vec![],
+        ),
+    )?;
+    assert_eq!(r.events[0].ty, "target_allowlist_updated");
+    let allowlist: TargetAllowlistResponse = from_json(&query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::TargetAllowlist {
+            start_after: None,
+            limit: None,
+        },
+    )?)?;
+    assert_eq!(
+        allowlist,
+        TargetAllowlistResponse {
+            enabled: true,
+            targets: vec![AllowedTarget {
+                address: allowed.clone(),
+                limits,
+            }],
+        }
+    );
+
+    assert_eq!(
+        relay(&mut deps, &sks, &logic_call(2, &other, vec![])).unwrap_err(),
+        ContractError::TargetNotAllowed {
+            target: other.clone()
+        }
+    );
+    assert_eq!(
+        relay(
+            &mut deps,
+            &sks,
+            &logic_call(2, &allowed, coins(101, "ugrain"))
+        )
+        .unwrap_err(),
+        ContractError::InsufficientFunds {
+            denom: "ugrain".to_string(),
+            available: Uint128::from(100u8),
+            required: Uint128::from(101u8),
+        }
+    );
+
+    let r = relay(
+        &mut deps,
+        &sks,
+        &logic_call(2, &allowed, coins(40, "ugrain")),
+    )?;
+    assert_matches!(
+        &r.messages[0].msg,
+        CosmosMsg::Wasm(WasmMsg::Execute { funds, .. }) if funds == &coins(40, "ugrain")
+    );
+    assert_eq!(
+        usage(&deps, mock_env(), &allowed),
+        Some(TargetUsage {
+            window_start: mock_env().block.time.seconds(),
+            calls: 1,
+            funds: coins(40, "ugrain"),
+        })
+    );
+    assert_eq!(
+        relay(
+            &mut deps,
+            &sks,
+            &logic_call(3, &allowed, coins(70, "ugrain"))
+        )
+        .unwrap_err(),
+        ContractError::FundsLimitExceeded {
+            target: allowed.clone(),
+            denom: "ugrain".to_string(),
+            limit: Uint128::from(100u8),
+            used: Uint128::from(110u8),
+        }
+    );
+    relay(&mut deps, &sks, &logic_call(4, &allowed, vec![]))?;
+    assert_eq!(
+        relay(&mut deps, &sks, &logic_call(5, &allowed, vec![])).unwrap_err(),
+        ContractError::CallLimitExceeded {
+            target: allowed.clone(),
+            max_calls: 2,
+            window: 60,
+        }
+    );
+
+    // Usage starts over in the next window.
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(60);
+    assert_eq!(
+        usage(&deps, env.clone(), &allowed),
+        Some(TargetUsage {
+            window_start: env.block.time.seconds(),
+            calls: 0,
+            funds: vec![],
+        })
+    );
+    let logic_call = |message_id: u8, target: &Addr| ExecutePayload::SubmitLogicCall {
+        logic_call_args: LogicCallArgs {
+            contract_address: target.clone(),
+            payload: "".to_string(),
+            payload_binary: None,
+            funds: vec![],
+        },
+        message_id: Uint256::from(message_id),
+        smart_contract_id: smart_contract_id.clone(),
+        deadline: (env.block.time.seconds() + 1).into(),
+        relayer_reward: None,
+    };
+    relay_at(&mut deps, env.clone(), &sks, &logic_call(5, &allowed))?;
+
+    // Without the allowlist any target may be called again.
+    let mut update = update(6, Some(false), vec![], vec![allowed.clone()]);
+    if let ExecutePayload::UpdateTargetAllowlist { deadline, .. } = &mut update {
+        *deadline = (env.block.time.seconds() + 1).into();
+    }
+    relay_at(&mut deps, env.clone(), &sks, &update)?;
+    assert_eq!(usage(&deps, env.clone(), &allowed), None);
+    relay_at(&mut deps, env.clone(), &sks, &logic_call(7, &other))?;
+    Ok(())
+}
+
+#[test]
+fn logic_actions() -> Result<()> {
+    let (mut deps, sks) = setup()?;
+    let smart_contract_id = Addr::unchecked("contract0000");
+    let deadline = mock_env().block.time.seconds() + 1;
+    let submit = |message_id: u8, action| ExecutePayload::SubmitLogicAction {
+        action,
+        message_id: Uint256::from(message_id),
+        smart_contract_id: smart_contract_id.clone(),
+        deadline: deadline.into(),
+        relayer_reward: None,
+    };
+    deps.querier
+        .update_balance(MOCK_CONTRACT_ADDR, coins(1000, "ugrain"));
+
+    let timeout = IbcTimeout::with_timestamp(mock_env().block.time.plus_seconds(600));
+    let actions = vec![
+        (
+            LogicCallAction::BankSend {
+                to_address: Addr::unchecked("user0000"),
+                amount: coins(10, "ugrain"),
+            },
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "user0000".to_string(),
+                amount: coins(10, "ugrain"),
+            }),
+        ),
+        (
+            LogicCallAction::IbcTransfer {
+                channel_id: "channel-0".to_string(),
+                to_address: "osmo1user".to_string(),
+                amount: coin(20, "ugrain"),
+                timeout: timeout.clone(),
+            },
+            CosmosMsg::Ibc(IbcMsg::Transfer {
+                channel_id: "channel-0".to_string(),
+                to_address: "osmo1user".to_string(),
+                amount: coin(20, "ugrain"),
+                timeout,
+            }),
+        ),
+        (
+            LogicCallAction::Instantiate2 {
+                admin: None,
+                code_id: 7,
+                msg: Binary(b"{}".to_vec()),
+                funds: vec![],
+                label: "pool".to_string(),
+                salt: Binary(b"salt".to_vec()),
+            },
+            CosmosMsg::Wasm(WasmMsg::Instantiate2 {
+                admin: None,
+                code_id: 7,
+                label: "pool".to_string(),
+                msg: Binary(b"{}".to_vec()),
+                funds: vec![],
+                salt: Binary(b"salt".to_vec()),
+            }),
+        ),
+        (
+            LogicCallAction::Redelegate {
+                src_validator: "valoper0000".to_string(),
+                dst_validator: "valoper0001".to_string(),
+                amount: coin(30, "ugrain"),
+            },
+            CosmosMsg::Staking(StakingMsg::Redelegate {
+                src_validator: "valoper0000".to_string(),
+                dst_validator: "valoper0001".to_string(),
+                amount: coin(30, "ugrain"),
+            }),
+        ),
+    ];
+    for (message_id, (action, expected)) in (1..).zip(actions) {
+        let name = action.name();
+        let r = relay(&mut deps, &sks, &submit(message_id, action))?;
+        assert_eq!(r.messages[0].msg, expected);
+        assert_eq!(
+            r.events[0].attributes[1],
+            cosmwasm_std::attr("action", name)
+        );
+    }
+
+    // Actions are bound by the same checks as logic calls.
+    let delegate = |amount| LogicCallAction::Delegate {
+        validator: "valoper0000".to_string(),
+        amount: coin(amount, "ugrain"),
+    };
+    assert_eq!(
+        relay(&mut deps, &sks, &submit(1, delegate(1))).unwrap_err(),
+        ContractError::MessageIdUsed {
+            message_id: Uint256::from(1u8),
+        }
+    );
+    assert_eq!(
+        relay(&mut deps, &sks, &submit(5, delegate(1001))).unwrap_err(),
+        ContractError::InsufficientFunds {
+            denom: "ugrain".to_string(),
+            available: Uint128::from(1000u16),
+            required: Uint128::from(1001u16),
+        }
+    );
+    relay(
+        &mut deps,
+        &sks,
+        &ExecutePayload::UpdateTargetAllowlist {
+            enabled: Some(true),
+            allow: vec![AllowedTarget {
+                address: Addr::unchecked("valoper0000"),
+                limits: TargetLimits::default(),
+            }],
+            remove: vec![],
+            message_id: Uint256::from(6u8),
+            smart_contract_id: smart_contract_id.clone(),
+            deadline: deadline.into(),
+        },
+    )?;
+    relay(&mut deps, &sks, &submit(7, delegate(100)))?;
+    assert_eq!(
+        relay(
+            &mut deps,
+            &sks,
+            &submit(
+                8,
+                LogicCallAction::Instantiate {
+                    admin: None,
+                    code_id: 7,
+                    msg: Binary(b"{}".to_vec()),
+                    funds: vec![],
+                    label: "pool".to_string(),
+                }
+            )
+        )
+        .unwrap_err(),
+        ContractError::UntargetedAction {
+            action: "instantiate".to_string()
+        }
+    );
+    Ok(())
+}
+
+#[test]
+fn logic_call_payloads() -> Result<()> {
+    let (mut deps, sks) = setup()?;
+    let text = r#"{"contract_address":"addr109","payload":"{\"ping\":{}}"}"#;
+    let binary = r#"{"contract_address":"addr109","payload_binary":"AAEC"}"#;
+    let text: LogicCallArgs = serde_json::from_str(text)?;
+    let binary: LogicCallArgs = serde_json::from_str(binary)?;
+    assert_eq!(text.payload_binary, None);
+    assert_eq!(binary.payload, "");
+    assert_eq!(binary.payload_binary, Some(Binary(vec![0, 1, 2])));
+    // The old string form serializes exactly as before.
+    assert_eq!(
+        serde_json::to_string(&text)?,
+        r#"{"contract_address":"addr109","payload":"{\"ping\":{}}"}"#
+    );
+
+    let submit = |message_id: u8, logic_call_args| ExecutePayload::SubmitLogicCall {
+        logic_call_args,
+        message_id: Uint256::from(message_id),
+        smart_contract_id: Addr::unchecked("contract0000"),
+        deadline: (mock_env().block.time.seconds() + 1).into(),
+        relayer_reward: None,
+    };
+    let executed = |r: Response| match &r.messages[0].msg {
+        CosmosMsg::Wasm(WasmMsg::Execute { msg, .. }) => msg.clone(),
+        _ => panic!("Expected an execute message."),
+    };
+    let r = relay(&mut deps, &sks, &submit(1, text.clone()))?;
+    assert_eq!(executed(r), Binary(br#"{"ping":{}}"#.to_vec()));
+    let r = relay(&mut deps, &sks, &submit(2, binary.clone()))?;
+    assert_eq!(executed(r), Binary(vec![0, 1, 2]));
+    assert_eq!(
+        relay(
+            &mut deps,
+            &sks,
+            &submit(
+                3,
+                LogicCallArgs {
+                    payload_binary: binary.payload_binary,
+                    ..text
+                }
+            )
+        )
+        .unwrap_err(),
+        ContractError::AmbiguousPayload
+    );
+    Ok(())
+}
+
+#[test]
+fn deadlines() -> Result<()> {
+    assert_eq!(
+        serde_json::from_str::<Deadline>("123")?,
+        Deadline::Time(123)
+    );
+    assert_eq!(
+        serde_json::from_str::<Deadline>(r#"{"height":5}"#)?,
+        Deadline::Bounds {
+            time: None,
+            height: Some(5),
+        }
+    );
+    assert_eq!(
+        serde_json::to_string(&Deadline::Bounds {
+            time: Some(1),
+            height: Some(2),
+        })?,
+        r#"{"time":1,"height":2}"#
+    );
+
+    let (mut deps, sks) = setup()?;
+    let env = mock_env();
+    let (now, height) = (env.block.time.seconds(), env.block.height);
+    let pause = |message_id: u8, time, height| ExecutePayload::SetPaused {
+        paused: PauseState::default(),
+        message_id: Uint256::from(message_id),
+        smart_contract_id: Addr::unchecked("contract0000"),
+        deadline: Deadline::Bounds { time, height },
+    };
+
+    relay(&mut deps, &sks, &pause(1, None, Some(height + 1)))?;
+    relay(&mut deps, &sks, &pause(2, Some(now + 1), Some(height + 1)))?;
+    assert_eq!(
+        relay(&mut deps, &sks, &pause(3, Some(now + 1), Some(height))).unwrap_err(),
+        ContractError::HeightTimeout {
+            deadline_height: height,
+            block_height: height,
+        }
+    );
+    assert_eq!(
+        relay(&mut deps, &sks, &pause(3, Some(now), Some(height + 1))).unwrap_err(),
+        ContractError::Timeout {
+            deadline: now,
+            block_time: now,
+        }
+    );
+    assert_eq!(
+        relay(
+            &mut deps,
+            &sks,
+            &pause(3, None, Some(height + MAX_HEIGHT_HORIZON + 1))
+        )
+        .unwrap_err(),
+        ContractError::HeightDeadlineTooFar {
+            deadline_height: height + MAX_HEIGHT_HORIZON + 1,
+            block_height: height,
+            max_horizon: MAX_HEIGHT_HORIZON,
+        }
+    );
+    assert_eq!(
+        relay(&mut deps, &sks, &pause(3, None, None)).unwrap_err(),
+        ContractError::EmptyDeadline
+    );
+
+    // Ids with only a height deadline are pruned once it is reached.
+    let mut block = env.block.clone();
+    block.height += 1;
+    use_message_id(
+        &mut deps.storage,
+        &instantiated(),
+        &block,
+        Uint256::from(4u8),
+        Deadline::Bounds {
+            time: None,
+            height: Some(block.height + 1),
+        },
+        None,
+    )?;
+    let stored = |deps: &OwnedDeps<_, _, _>| {
+        instantiated()
+            .message_id_used()
+            .keys(&deps.storage, None, None, cosmwasm_std::Order::Ascending)
+            .count()
+    };
+    assert_eq!(stored(&deps), 2);
+    block.height += 1;
+    use_message_id(
+        &mut deps.storage,
+        &instantiated(),
+        &block,
+        Uint256::from(5u8),
+        (now + 1).into(),
+        None,
+    )?;
+    assert_eq!(stored(&deps), 2);
+
+    // Signed valset updates can be given a deadline too.
+    let (_, validators, powers) = keys(8);
+    let update = |deadline| ExecutePayload::UpdateValset {
+        valset: Valset {
+            valset_id: ValsetId(Uint256::from(1u8)),
+            validators: validators.clone(),
+            powers: powers.clone(),
+            key_types: vec![],
+        },
+        smart_contract_id: Addr::unchecked("contract0000"),
+        deadline: Some(deadline),
+    };
+    assert_eq!(
+        relay(&mut deps, &sks, &update(now.into())).unwrap_err(),
+        ContractError::Timeout {
+            deadline: now,
+            block_time: now,
+        }
+    );
+    relay(&mut deps, &sks, &update((now + 1).into()))?;
+    Ok(())
+}
+
+#[test]
+fn compact_consensus() -> Result<()> {
+    let (mut deps, sks) = setup()?;
+    let pause = |message_id: u8| ExecutePayload::SetPaused {
+        paused: PauseState::default(),
+        message_id: Uint256::from(message_id),
+        smart_contract_id: Addr::unchecked("contract0000"),
+        deadline: (mock_env().block.time.seconds() + 1).into(),
+    };
+    // Only the first 6 of 8 validators sign, which is enough.
+    let compact = |message_id: u8| -> Result<(CompactConsensus, Binary)> {
+        match execute_msg_signed_by(&sks, 6, &pause(message_id))? {
+            ExecuteMsg::Consensus {
+                consensus: ConsensusEncoding::Full(consensus),
+                payload,
+            } => Ok(((&consensus).into(), payload)),
+            _ => panic!("Expected a full consensus."),
+        }
+    };
+
+    let (consensus, payload) = compact(1)?;
+    assert_eq!(consensus.signer_bitmap, Binary(vec![0b0011_1111]));
+    assert_eq!(consensus.signatures.len(), 6 * 64);
+    // The compact form is accepted on the wire under the same `consensus` key.
+    let msg: ExecuteMsg = cosmwasm_std::from_json(&serde_json::to_vec(&serde_json::json!({
+        "consensus": consensus,
+        "payload": payload,
+    }))?)?;
+    relay_msg(&mut deps, mock_env(), msg)?;
+
+    // Signatures with a recovery id are accepted too.
+    let (mut consensus, payload) = compact(2)?;
+    consensus.signatures = Binary(
+        consensus
+            .signatures
+            .chunks(64)
+            .flat_map(|signature| [signature, &[27]].concat())
+            .collect(),
+    );
+    relay_msg(
+        &mut deps,
+        mock_env(),
+        ExecuteMsg::Consensus {
+            consensus: consensus.into(),
+            payload,
+        },
+    )?;
+
+    let (consensus, payload) = compact(3)?;
+    let mut malformed = |signer_bitmap: Vec<u8>, signatures: &[u8]| {
+        relay_msg(
+            &mut deps,
+            mock_env(),
+            ExecuteMsg::Consensus {
+                consensus: (CompactConsensus {
+                    signer_bitmap: Binary(signer_bitmap),
+                    signatures: Binary(signatures.to_vec()),
+                })
+                .into(),
+                payload: payload.clone(),
+            },
+        )
+        .unwrap_err()
+    };
+    assert_matches!(
+        malformed(vec![0b0011_1111, 0], &consensus.signatures),
+        ContractError::MalformedConsensus { .. }
+    );
+    assert_matches!(
+        malformed(vec![0b0011_1111], &consensus.signatures[1..]),
+        ContractError::MalformedConsensus { .. }
+    );
+    assert_matches!(
+        malformed(vec![0b0011_1111], &[]),
+        ContractError::MalformedConsensus { .. }
+    );
+    assert_eq!(
+        malformed(vec![0b0001_1111], &consensus.signatures[..5 * 64]),
+        ContractError::InsufficientPower {
+            power: 5 * (1 << 29),
+            threshold: 2_863_311_530,
+        }
+    );
+    // Signatures are matched to validators by the bitmap.
+    assert_eq!(
+        malformed(vec![0b0111_1110], &consensus.signatures),
+        ContractError::InvalidSignature { index: 1 }
+    );
+    Ok(())
+}
+
+#[test]
+fn used_message_ids() -> Result<()> {
+    let (mut deps, sks) = setup()?;
+    let env = mock_env();
+    let deadline = Deadline::Time(env.block.time.seconds() + 1);
+    let target = Addr::unchecked("addr109");
+    for payload in [
+        ExecutePayload::SubmitLogicCall {
+            logic_call_args: LogicCallArgs {
+                contract_address: target.clone(),
+                payload: "{}".to_string(),
+                payload_binary: None,
+                funds: vec![],
+            },
+            message_id: Uint256::from(300u16),
+            smart_contract_id: Addr::unchecked("contract0000"),
+            deadline,
+            relayer_reward: None,
+        },
+        ExecutePayload::SetPaused {
+            paused: PauseState::default(),
+            message_id: Uint256::from(3u8),
+            smart_contract_id: Addr::unchecked("contract0000"),
+            deadline,
+        },
+    ] {
+        execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("relayer0000", &[]),
+            execute_msg(&sks, &payload)?,
+        )?;
+    }
+    let used = |message_id: u16| -> Result<Option<UsedMessageId>> {
+        Ok(from_json(&query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::UsedMessageId {
+                message_id: Uint256::from(message_id),
+                target: None,
+                smart_contract_id: None,
+            },
+        )?)?)
+    };
+    let logic_call = UsedMessageId {
+        message_id: Uint256::from(300u16),
+        deadline,
+        height: Some(env.block.height),
+        time: Some(env.block.time.seconds()),
+        target: Some(target),
+    };
+    assert_eq!(used(300)?, Some(logic_call.clone()));
+    assert_eq!(used(4)?, None);
+    let pause = used(3)?.unwrap();
+    assert_eq!(pause.target, None);
+
+    // Ids are listed in numeric order.
+    let page = |start_after: Option<u16>, limit| -> Result<Vec<UsedMessageId>> {
+        Ok(from_json(&query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::UsedMessageIds {
+                start_after: start_after.map(Uint256::from),
+                limit,
+                target: None,
+                smart_contract_id: None,
+            },
+        )?)?)
+    };
+    assert_eq!(page(None, None)?, vec![pause.clone(), logic_call.clone()]);
+    assert_eq!(page(None, Some(1))?, vec![pause]);
+    assert_eq!(page(Some(3), None)?, vec![logic_call]);
+    assert_eq!(page(Some(300), None)?, vec![]);
+    Ok(())
+}
+
+#[test]
+fn thresholds() -> Result<()> {
+    let (mut deps, sks) = setup()?;
+    let smart_contract_id = Addr::unchecked("contract0000");
+    let deadline = Deadline::Time(mock_env().block.time.seconds() + 1);
+    let set_thresholds = |message_id: u8, thresholds| ExecutePayload::SetThresholds {
+        thresholds,
+        message_id: Uint256::from(message_id),
+        smart_contract_id: smart_contract_id.clone(),
+        deadline,
+    };
+    let defaults: Thresholds = from_json(&query(deps.as_ref(), mock_env(), QueryMsg::Thresholds)?)?;
+    assert_eq!(defaults.max(), 2_863_311_530);
+
+    assert_eq!(
+        relay_signed_by(
+            &mut deps,
+            &sks,
+            8,
+            &set_thresholds(
+                1,
+                Thresholds {
+                    admin: 1 << 31,
+                    ..defaults.clone()
+                }
+            )
+        )
+        .unwrap_err(),
+        ContractError::InvalidThreshold {
+            name: "admin".to_string(),
+            threshold: 1 << 31,
+            min: 1 << 31,
+            max: 1 << 32,
+        }
+    );
+    // Every one of the 8 validators holds 2**29.
+    let thresholds = Thresholds {
+        fund_release: 7 << 29,
+        migrate: 1 << 32,
+        ..defaults
+    };
+    relay_signed_by(&mut deps, &sks, 8, &set_thresholds(1, thresholds.clone()))?;
+    let stored: Thresholds = from_json(&query(deps.as_ref(), mock_env(), QueryMsg::Thresholds)?)?;
+    assert_eq!(stored, thresholds);
+
+    let migrate = |message_id: u8| ExecutePayload::Migrate {
+        new_code_id: 2,
+        msg: Binary(b"{}".to_vec()),
+        message_id: Uint256::from(message_id),
+        smart_contract_id: smart_contract_id.clone(),
+        deadline,
+    };
+    assert_eq!(
+        relay_signed_by(&mut deps, &sks, 7, &migrate(2)).unwrap_err(),
+        ContractError::InsufficientPower {
+            power: 7 << 29,
+            threshold: 1 << 32,
+        }
+    );
+    relay_signed_by(&mut deps, &sks, 8, &migrate(2))?;
+
+    deps.querier
+        .update_balance(MOCK_CONTRACT_ADDR, coins(100, "ugrain"));
+    let logic_call = |message_id: u8, funds| ExecutePayload::SubmitLogicCall {
+        logic_call_args: LogicCallArgs {
+            contract_address: Addr::unchecked("addr109"),
+            payload: "{}".to_string(),
+            payload_binary: None,
+            funds,
+        },
+        message_id: Uint256::from(message_id),
+        smart_contract_id: smart_contract_id.clone(),
+        deadline,
+        relayer_reward: None,
+    };
+    relay_signed_by(&mut deps, &sks, 6, &logic_call(3, vec![]))?;
+    assert_eq!(
+        relay_signed_by(&mut deps, &sks, 6, &logic_call(4, coins(1, "ugrain"))).unwrap_err(),
+        ContractError::InsufficientPower {
+            power: 6 << 29,
+            threshold: 7 << 29,
+        }
+    );
+    relay_signed_by(&mut deps, &sks, 7, &logic_call(4, coins(1, "ugrain")))?;
+
+    // Changing thresholds takes the highest one, and valsets must be able to reach it.
+    assert_matches!(
+        relay_signed_by(&mut deps, &sks, 7, &set_thresholds(5, thresholds)).unwrap_err(),
+        ContractError::InsufficientPower { .. }
+    );
+    let (_, validators, powers) = keys(7);
+    assert_eq!(
+        relay_signed_by(
+            &mut deps,
+            &sks,
+            8,
+            &ExecutePayload::UpdateValset {
+                valset: Valset {
+                    valset_id: ValsetId(Uint256::from(1u8)),
+                    validators,
+                    powers,
+                    key_types: vec![],
+                },
+                smart_contract_id: smart_contract_id.clone(),
+                deadline: None,
+            }
+        )
+        .unwrap_err(),
+        ContractError::Valset(ValsetError::InsufficientPower {
+            total: 7 * ((1 << 32) / 7),
+            threshold: 1 << 32,
+        })
+    );
+    Ok(())
+}
+
+#[test]
+fn valset_log() -> Result<()> {
+    let (mut deps, mut sks) = setup()?;
+    let mut env = mock_env();
+    let log = |deps: &OwnedDeps<_, _, _>, start_after: Option<u16>, limit| -> Result<Vec<_>> {
+        Ok(from_json::<Vec<ValsetLogEntry>>(&query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::ValsetLog {
+                start_after: start_after.map(|id| ValsetId(Uint256::from(id))),
+                limit,
+                smart_contract_id: None,
+            },
+        )?)?)
+    };
+    let initial = log(&deps, None, None)?;
+    assert_eq!(initial.len(), 1);
+    assert_eq!(initial[0].valset_id, ValsetId(Uint256::zero()));
+    assert_eq!(initial[0].validators, 8);
+    assert_eq!(initial[0].total_power, 1 << 32);
+
+    // Rotate through single validator valsets until the oldest ones are forgotten.
+    for valset_id in 1..=101u16 {
+        let (new_sks, validators, _) = keys(1);
+        let valset = Valset {
+            valset_id: ValsetId(Uint256::from(valset_id)),
+            validators,
+            powers: vec![u32::MAX],
+            key_types: vec![],
+        };
+        env.block.height += 1;
+        let r = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info("relayer0000", &[]),
+            execute_msg(
+                &sks,
+                &ExecutePayload::UpdateValset {
+                    valset: valset.clone(),
+                    smart_contract_id: Addr::unchecked("contract0000"),
+                    deadline: None,
+                },
+            )?,
+        )?;
+        if valset_id == 1 {
+            assert_eq!(
+                r.events,
+                [Event::new("valset_updated")
+                    .add_attribute("old_valset_id", "0")
+                    .add_attribute("new_valset_id", "1")
+                    .add_attribute("validators", "1")
+                    .add_attribute("total_power", u32::MAX.to_string())
+                    .add_attribute("hash", hex::encode(crate::msg::valset_hash(&valset)))]
+            );
+        }
+        sks = new_sks;
+    }
+
+    let page = log(&deps, None, Some(3))?;
+    assert_eq!(
+        page.iter()
+            .map(|entry| &entry.valset_id)
+            .collect::<Vec<_>>(),
+        [2u16, 3, 4]
+            .map(|id| ValsetId(Uint256::from(id)))
+            .iter()
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(page[0].height, mock_env().block.height + 2);
+    assert_eq!(page[0].total_power, u32::MAX as u64);
+    let last = log(&deps, Some(100), None)?;
+    assert_eq!(last.len(), 1);
+    assert_eq!(last[0].valset_id, ValsetId(Uint256::from(101u16)));
+    assert_eq!(last[0].height, env.block.height);
+    Ok(())
+}
+
+#[test]
+fn namespaces() -> Result<()> {
+    let (mut deps, sks) = setup()?;
+    let (other_sks, validators, powers) = keys(4);
+    let other = Addr::unchecked("contract0001");
+    let deadline: Deadline = (mock_env().block.time.seconds() + 1).into();
+    let logic_call = |smart_contract_id: &Addr, message_id: u8| ExecutePayload::SubmitLogicCall {
+        logic_call_args: LogicCallArgs {
+            contract_address: Addr::unchecked("target0000"),
+            payload: "{}".to_string(),
+            payload_binary: None,
+            funds: vec![],
+        },
+        message_id: Uint256::from(message_id),
+        smart_contract_id: smart_contract_id.clone(),
+        deadline,
+        relayer_reward: None,
+    };
+
+    // Unknown instances are rejected.
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("relayer0000", &[]),
+        execute_msg(&other_sks, &logic_call(&other, 1))?,
+    );
+    assert_eq!(
+        r.unwrap_err(),
+        ContractError::WrongSmartContractId {
+            expected: Addr::unchecked("contract0000"),
+            actual: other.clone(),
+        }
+    );
+
+    let valset = Valset {
+        valset_id: ValsetId(Uint256::from(5u8)),
+        validators,
+        powers,
+        key_types: vec![],
+    };
+    let create = |message_id: u8| ExecutePayload::CreateNamespace {
+        new_smart_contract_id: other.clone(),
+        valset: valset.clone(),
+        message_id: Uint256::from(message_id),
+        smart_contract_id: Addr::unchecked("contract0000"),
+        deadline,
+    };
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("relayer0000", &[]),
+        execute_msg(&sks, &create(1))?,
+    )?;
+    assert_eq!(r.events[0].ty, "namespace_created");
+    assert_eq!(r.events[0].attributes[0].value, "contract0001");
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("relayer0000", &[]),
+        execute_msg(&sks, &create(2))?,
+    );
+    assert_eq!(
+        r.unwrap_err(),
+        ContractError::NamespaceExists {
+            smart_contract_id: other.clone(),
+        }
+    );
+
+    // The new namespace is signed by its own valset and has its own message ids.
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("relayer0000", &[]),
+        execute_msg(&sks, &logic_call(&other, 1))?,
+    );
+    assert_matches!(r.unwrap_err(), ContractError::Valset(_));
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("relayer0000", &[]),
+        execute_msg(&other_sks, &logic_call(&other, 1))?,
+    )?;
+    let r = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("relayer0000", &[]),
+        execute_msg(&other_sks, &logic_call(&other, 1))?,
+    );
+    assert_eq!(
+        r.unwrap_err(),
+        ContractError::MessageIdUsed {
+            message_id: Uint256::from(1u8),
+        }
+    );
+
+    let namespaces: Vec<Addr> = from_json(&query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::Namespaces {
+            start_after: None,
+            limit: None,
+        },
+    )?)?;
+    assert_eq!(namespaces, vec![other.clone()]);
+    let valset: Valset = from_json(&query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::Valset {
+            smart_contract_id: Some(other.clone()),
+        },
+    )?)?;
+    assert_eq!(valset.valset_id, ValsetId(Uint256::from(5u8)));
+    let valset_id: ValsetId = from_json(&query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::NamespaceValsetId {
+            smart_contract_id: other.clone(),
+        },
+    )?)?;
+    assert_eq!(valset_id, ValsetId(Uint256::from(5u8)));
+    let log: Vec<ValsetLogEntry> = from_json(&query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::ValsetLog {
+            start_after: None,
+            limit: None,
+            smart_contract_id: Some(other.clone()),
+        },
+    )?)?;
+    assert_eq!(log.len(), 1);
+    assert_eq!(log[0].validators, 4);
+    for (smart_contract_id, used) in [(None, 1), (Some(other), 1)] {
+        let ids: Vec<UsedMessageId> = from_json(&query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::UsedMessageIds {
+                start_after: None,
+                limit: None,
+                target: None,
+                smart_contract_id,
+            },
+        )?)?;
+        assert_eq!(ids.len(), used);
+    }
+    let r = query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::Valset {
+            smart_contract_id: Some(Addr::unchecked("contract0002")),
+        },
+    );
+    assert!(r.is_err());
+    Ok(())
+}
+
+#[test]
+fn contract_wide_payloads() -> Result<()> {
+    let (mut deps, sks) = setup()?;
+    let (other_sks, validators, powers) = keys(4);
+    let other = Addr::unchecked("contract0001");
+    let deadline: Deadline = (mock_env().block.time.seconds() + 1).into();
+    let valset = Valset {
+        valset_id: ValsetId(Uint256::from(5u8)),
+        validators,
+        powers,
+        key_types: vec![],
+    };
+    relay(
+        &mut deps,
+        &sks,
+        &ExecutePayload::CreateNamespace {
+            new_smart_contract_id: other.clone(),
+            valset: valset.clone(),
+            message_id: Uint256::from(1u8),
+            smart_contract_id: Addr::unchecked("contract0000"),
+            deadline,
+        },
+    )?;
+    let thresholds: Thresholds =
+        from_json(&query(deps.as_ref(), mock_env(), QueryMsg::Thresholds)?)?;
+
+    // Payloads changing state every namespace shares are only accepted from the instantiated
+    // one, whatever the threshold of the namespace's valset.
+    let message_id = Uint256::from(1u8);
+    let smart_contract_id = other.clone();
+    let payloads = [
+        ExecutePayload::DeployWrappedToken {
+            origin_asset_id: "dead".to_string(),
+            code_id: 7,
+            name: "Wrapped Dead".to_string(),
+            symbol: "WDEAD".to_string(),
+            decimals: 6,
+            message_id,
+            smart_contract_id: smart_contract_id.clone(),
+            deadline,
+        },
+        ExecutePayload::TransferWrappedToken {
+            origin_asset_id: "dead".to_string(),
+            recipient: Addr::unchecked("user0000"),
+            amount: Uint128::new(1),
+            message_id,
+            smart_contract_id: smart_contract_id.clone(),
+            deadline,
+            relayer_reward: None,
+        },
+        ExecutePayload::SetPaused {
+            paused: PauseState {
+                logic_calls: true,
+                bridge: true,
+            },
+            message_id,
+            smart_contract_id: smart_contract_id.clone(),
+            deadline,
+        },
+        ExecutePayload::UpdateTargetAllowlist {
+            enabled: Some(true),
+            allow: vec![],
+            remove: vec![],
+            message_id,
+            smart_contract_id: smart_contract_id.clone(),
+            deadline,
+        },
+        ExecutePayload::SetNonceMode {
+            target: Addr::unchecked("target0000"),
+            mode: NonceMode::Ordered,
+            message_id,
+            smart_contract_id: smart_contract_id.clone(),
+            deadline,
+        },
+        ExecutePayload::SetThresholds {
+            thresholds: thresholds.clone(),
+            message_id,
+            smart_contract_id: smart_contract_id.clone(),
+            deadline,
+        },
+        ExecutePayload::Migrate {
+            new_code_id: 7,
+            msg: to_json_binary(&MigrateMsg {})?,
+            message_id,
+            smart_contract_id: smart_contract_id.clone(),
+            deadline,
+        },
+        ExecutePayload::CreateNamespace {
+            new_smart_contract_id: Addr::unchecked("contract0002"),
+            valset,
+            message_id,
+            smart_contract_id,
+            deadline,
+        },
+    ];
+    for payload in &payloads {
+        assert_eq!(
+            relay(&mut deps, &other_sks, payload).unwrap_err(),
+            ContractError::ContractWidePayload {
+                expected: Addr::unchecked("contract0000"),
+                actual: other.clone(),
+            },
+            "{payload:?}"
+        );
+    }
+    assert_eq!(
+        from_json::<PauseState>(&query(deps.as_ref(), mock_env(), QueryMsg::Paused)?)?,
+        PauseState::default()
+    );
+    assert_eq!(
+        from_json::<Thresholds>(&query(deps.as_ref(), mock_env(), QueryMsg::Thresholds)?)?,
+        thresholds
+    );
+    Ok(())
+}
+
+#[test]
+fn ordered_nonces() -> Result<()> {
+    let (mut deps, sks) = setup()?;
+    let ordered = Addr::unchecked("ordered0000");
+    let deadline: Deadline = (mock_env().block.time.seconds() + 1).into();
+    let call = |target: &Addr, message_id: u8| BatchedLogicCall {
+        logic_call_args: LogicCallArgs {
+            contract_address: target.clone(),
+            payload: "{}".to_string(),
+            payload_binary: None,
+            funds: vec![],
+        },
+        message_id: Uint256::from(message_id),
+    };
+    let batch = |logic_calls: Vec<BatchedLogicCall>| ExecutePayload::SubmitLogicCallBatch {
+        logic_calls,
+        smart_contract_id: Addr::unchecked("contract0000"),
+        deadline,
+        relayer_reward: None,
+    };
+    let set_nonce_mode = |mode, message_id: u8| ExecutePayload::SetNonceMode {
+        target: ordered.clone(),
+        mode,
+        message_id: Uint256::from(message_id),
+        smart_contract_id: Addr::unchecked("contract0000"),
+        deadline,
+    };
+    let next_nonce = |deps: &OwnedDeps<_, _, _>| -> Result<NextNonce> {
+        Ok(from_json(&query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::NextNonce {
+                target: ordered.clone(),
+                smart_contract_id: None,
+            },
+        )?)?)
+    };
+
+    assert_eq!(
+        next_nonce(&deps)?,
+        NextNonce {
+            mode: NonceMode::Unordered,
+            next_nonce: Uint256::zero(),
+        }
+    );
+    let r = relay(&mut deps, &sks, &set_nonce_mode(NonceMode::Ordered, 10))?;
+    assert_eq!(
+        r.events,
+        [Event::new("nonce_mode")
+            .add_attribute("target", "ordered0000")
+            .add_attribute("mode", "ordered")]
+    );
+
+    // Nonces start from zero and may not be skipped, while other targets keep unordered ids.
+    assert_eq!(
+        relay(&mut deps, &sks, &batch(vec![call(&ordered, 1)])).unwrap_err(),
+        ContractError::NonceOutOfOrder {
+            target: ordered.clone(),
+            expected: Uint256::zero(),
+            actual: Uint256::one(),
+        }
+    );
+    let other = Addr::unchecked("other0000");
+    let ordered_batch = batch(vec![
+        call(&ordered, 0),
+        call(&other, 1),
+        call(&ordered, 1),
+        call(&ordered, 2),
+    ]);
+    relay(&mut deps, &sks, &ordered_batch)?;
+    assert_eq!(
+        next_nonce(&deps)?,
+        NextNonce {
+            mode: NonceMode::Ordered,
+            next_nonce: Uint256::from(3u8),
+        }
+    );
+
+    // Used nonces are queried by target, apart from the message ids of the same value.
+    let used_nonce = |nonce: u8| UsedMessageId {
+        message_id: Uint256::from(nonce),
+        deadline,
+        height: Some(mock_env().block.height),
+        time: Some(mock_env().block.time.seconds()),
+        target: Some(ordered.clone()),
+    };
+    let used: Option<UsedMessageId> = from_json(&query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::UsedMessageId {
+            message_id: Uint256::one(),
+            target: Some(ordered.clone()),
+            smart_contract_id: None,
+        },
+    )?)?;
+    assert_eq!(used, Some(used_nonce(1)));
+    let used: Option<UsedMessageId> = from_json(&query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::UsedMessageId {
+            message_id: Uint256::one(),
+            target: None,
+            smart_contract_id: None,
+        },
+    )?)?;
+    assert_eq!(used.and_then(|used| used.target), Some(other.clone()));
+    let used_nonces = |start_after: Option<u8>, target: &str| -> Result<Vec<UsedMessageId>> {
+        Ok(from_json(&query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::UsedMessageIds {
+                start_after: start_after.map(Uint256::from),
+                limit: None,
+                target: Some(Addr::unchecked(target)),
+                smart_contract_id: None,
+            },
+        )?)?)
+    };
+    assert_eq!(
+        used_nonces(None, "ordered0000")?,
+        [used_nonce(0), used_nonce(1), used_nonce(2)]
+    );
+    assert_eq!(
+        used_nonces(Some(0), "ordered0000")?,
+        [used_nonce(1), used_nonce(2)]
+    );
+    // Targets are not matched by prefix.
+    assert_eq!(used_nonces(None, "ordered")?, []);
+    assert_eq!(
+        relay(&mut deps, &sks, &batch(vec![call(&ordered, 2)])).unwrap_err(),
+        ContractError::NonceOutOfOrder {
+            target: ordered.clone(),
+            expected: Uint256::from(3u8),
+            actual: Uint256::from(2u8),
+        }
+    );
+    assert_eq!(
+        relay(&mut deps, &sks, &batch(vec![call(&other, 1)])).unwrap_err(),
+        ContractError::MessageIdUsed {
+            message_id: Uint256::one(),
+        }
+    );
+
+    // Back in unordered mode the nonce is kept, and ids are also checked against the nonces the
+    // target used.
+    relay(&mut deps, &sks, &set_nonce_mode(NonceMode::Unordered, 11))?;
+    assert_eq!(
+        relay(&mut deps, &sks, &ordered_batch).unwrap_err(),
+        ContractError::MessageIdUsed {
+            message_id: Uint256::zero(),
+        }
+    );
+    relay(
+        &mut deps,
+        &sks,
+        &batch(vec![call(&ordered, 7), call(&ordered, 5)]),
+    )?;
+    assert_eq!(
+        next_nonce(&deps)?,
+        NextNonce {
+            mode: NonceMode::Unordered,
+            next_nonce: Uint256::from(3u8),
+        }
+    );
+    Ok(())
+}
+
+#[test]
+fn nonce_mode_switch_replays() -> Result<()> {
+    let (mut deps, sks) = setup()?;
+    let target = Addr::unchecked("ordered0000");
+    let mut env = mock_env();
+    let deadline = |env: &Env| -> Deadline { (env.block.time.seconds() + 10).into() };
+    let call_to = |target: &Addr, message_id: u8, deadline| ExecutePayload::SubmitLogicCall {
+        logic_call_args: LogicCallArgs {
+            contract_address: target.clone(),
+            payload: "{}".to_string(),
+            payload_binary: None,
+            funds: vec![],
+        },
+        message_id: Uint256::from(message_id),
+        smart_contract_id: Addr::unchecked("contract0000"),
+        deadline,
+        relayer_reward: None,
+    };
+    let call = |message_id: u8, deadline: Deadline| call_to(&target, message_id, deadline);
+    let set_nonce_mode = |mode, message_id: u8, deadline| ExecutePayload::SetNonceMode {
+        target: target.clone(),
+        mode,
+        message_id: Uint256::from(message_id),
+        smart_contract_id: Addr::unchecked("contract0000"),
+        deadline,
+    };
+
+    // An unordered payload is not replayed once its id becomes the next nonce of its target.
+    let unordered = call(1, deadline(&env));
+    relay_at(&mut deps, env.clone(), &sks, &unordered)?;
+    relay_at(
+        &mut deps,
+        env.clone(),
+        &sks,
+        &set_nonce_mode(NonceMode::Ordered, 100, deadline(&env)),
+    )?;
+    relay_at(&mut deps, env.clone(), &sks, &call(0, deadline(&env)))?;
+    assert_eq!(
+        relay_at(&mut deps, env.clone(), &sks, &unordered).unwrap_err(),
+        ContractError::MessageIdUsed {
+            message_id: Uint256::one(),
+        }
+    );
+
+    // Once it has expired, the nonce is free for a new payload.
+    env.block.time = env.block.time.plus_seconds(10);
+    relay_at(&mut deps, env.clone(), &sks, &call(1, deadline(&env)))?;
+
+    // An ordered payload is not replayed as an unordered id.
+    let ordered = call(2, deadline(&env));
+    relay_at(&mut deps, env.clone(), &sks, &ordered)?;
+    relay_at(
+        &mut deps,
+        env.clone(),
+        &sks,
+        &set_nonce_mode(NonceMode::Unordered, 101, deadline(&env)),
+    )?;
+    assert_eq!(
+        relay_at(&mut deps, env.clone(), &sks, &ordered).unwrap_err(),
+        ContractError::MessageIdUsed {
+            message_id: Uint256::from(2u8),
+        }
+    );
+
+    // Nonces are remembered per target, so other targets may still use the same ids.
+    let other = call_to(&Addr::unchecked("other0000"), 2, deadline(&env));
+    relay_at(&mut deps, env.clone(), &sks, &other)?;
+
+    // Ids used by unversioned deployments do not record their target, so they are never nonces.
+    let legacy_target = Addr::unchecked("legacy0000");
+    let legacy = call_to(&legacy_target, 0, deadline(&env));
+    crate::state::LEGACY_MESSAGE_ID_USED.save(
+        &mut deps.storage,
+        Uint256::zero().to_be_bytes().to_vec(),
+        &(),
+    )?;
+    let set_legacy_ordered = ExecutePayload::SetNonceMode {
+        target: legacy_target,
+        mode: NonceMode::Ordered,
+        message_id: Uint256::from(102u8),
+        smart_contract_id: Addr::unchecked("contract0000"),
+        deadline: deadline(&env),
+    };
+    relay_at(&mut deps, env.clone(), &sks, &set_legacy_ordered)?;
+    assert_eq!(
+        relay_at(&mut deps, env, &sks, &legacy).unwrap_err(),
+        ContractError::MessageIdUsed {
+            message_id: Uint256::zero(),
+        }
+    );
+    Ok(())
+}
+
+#[test]
+fn config() -> Result<()> {
+    let (mut deps, sks) = setup()?;
+    let config = |deps: &OwnedDeps<_, _, _>| -> Result<ConfigResponse> {
+        Ok(from_json(&query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Config {
+                smart_contract_id: None,
+            },
+        )?)?)
+    };
+    assert_eq!(
+        config(&deps)?,
+        ConfigResponse {
+            smart_contract_id: Addr::unchecked("contract0000"),
+            valset_id: ValsetId(Uint256::zero()),
+            validators: 8,
+            total_power: 1 << 32,
+            thresholds: from_json(&query(deps.as_ref(), mock_env(), QueryMsg::Thresholds)?)?,
+            version: cw2::get_contract_version(&deps.storage)?,
+            paused: PauseState::default(),
+            features: vec![],
+        }
+    );
+
+    let deadline: Deadline = (mock_env().block.time.seconds() + 1).into();
+    for payload in [
+        ExecutePayload::UpdateTargetAllowlist {
+            enabled: Some(true),
+            allow: vec![],
+            remove: vec![],
+            message_id: Uint256::from(1u8),
+            smart_contract_id: Addr::unchecked("contract0000"),
+            deadline,
+        },
+        ExecutePayload::SetNonceMode {
+            target: Addr::unchecked("ordered0000"),
+            mode: NonceMode::Ordered,
+            message_id: Uint256::from(2u8),
+            smart_contract_id: Addr::unchecked("contract0000"),
+            deadline,
+        },
+    ] {
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("relayer0000", &[]),
+            execute_msg(&sks, &payload)?,
+        )?;
+    }
+    assert_eq!(
+        config(&deps)?.features,
+        [Feature::TargetAllowlist, Feature::OrderedNonces]
+    );
     Ok(())
 }
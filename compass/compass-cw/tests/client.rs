@@ -0,0 +1,153 @@
+//! Relays messages built by compass-cw-client, which can not be used from the unit tests since
+//! it depends on compass-cw itself.
+
+use compass_cw::contract::{execute, instantiate, query};
+use compass_cw::error::ContractError;
+use compass_cw::msg::{
+    Deadline, ExecuteMsg, ExecutePayload, InstantiateMsg, LogicCallArgs, PauseState, QueryMsg,
+    Thresholds, Valset, ValsetId,
+};
+use compass_cw_client::{sign_payload, ConsensusBuilder, Signer};
+use cosmwasm_std::testing::{
+    mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage,
+};
+use cosmwasm_std::{from_json, Addr, Binary, OwnedDeps, Response, Uint256};
+use eyre::Result;
+use k256::ecdsa::SigningKey;
+use secp256k1::rand::rngs::OsRng;
+
+type TestDeps = OwnedDeps<MockStorage, MockApi, MockQuerier>;
+
+fn relay(deps: &mut TestDeps, msg: ExecuteMsg) -> Result<Response, ContractError> {
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("relayer0000", &[]),
+        msg,
+    )
+}
+
+fn thresholds(deps: &TestDeps) -> Result<Thresholds> {
+    Ok(from_json(&query(
+        deps.as_ref(),
+        mock_env(),
+        QueryMsg::Thresholds,
+    )?)?)
+}
+
+fn deadline() -> Deadline {
+    Deadline::Time(mock_env().block.time.seconds() + 1)
+}
+
+/// The client builds exactly the messages compass accepts, at the threshold of each payload.
+#[test]
+fn consensus_matches_the_contract() -> Result<()> {
+    let keys: Vec<_> = (0..8).map(|_| SigningKey::random(&mut OsRng)).collect();
+    let valset = Valset {
+        valset_id: ValsetId(Uint256::zero()),
+        validators: keys.iter().map(|key| Binary(key.public_key())).collect(),
+        powers: vec![1 << 29; keys.len()],
+        key_types: vec![],
+    };
+    let mut deps = mock_dependencies();
+    instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("admin0000", &[]),
+        InstantiateMsg {
+            smart_contract_id: Addr::unchecked("contract0000"),
+            valset: valset.clone(),
+        },
+    )?;
+
+    // Admin payloads need more power than logic calls.
+    let raised = Thresholds {
+        logic_call: 5 << 29,
+        admin: 7 << 29,
+        ..thresholds(&deps)?
+    };
+    let set_thresholds = ExecutePayload::SetThresholds {
+        thresholds: raised.clone(),
+        message_id: Uint256::zero(),
+        smart_contract_id: Addr::unchecked("contract0000"),
+        deadline: deadline(),
+    };
+    let msg = sign_payload(&valset, &thresholds(&deps)?, &keys, &set_thresholds)?;
+    relay(&mut deps, msg)?;
+    let thresholds = thresholds(&deps)?;
+    assert_eq!(thresholds, raised);
+    // Builds messages whatever power signed them, as a faulty relayer might.
+    let unchecked = Thresholds {
+        update_valset: 0,
+        logic_call: 0,
+        fund_release: 0,
+        bridge: 0,
+        admin: 0,
+        migrate: 0,
+    };
+
+    let pause = |message_id| ExecutePayload::SetPaused {
+        paused: PauseState::default(),
+        message_id,
+        smart_contract_id: Addr::unchecked("contract0000"),
+        deadline: deadline(),
+    };
+    let logic_call = |message_id| ExecutePayload::SubmitLogicCall {
+        logic_call_args: LogicCallArgs {
+            contract_address: Addr::unchecked("target0000"),
+            payload: "{}".to_string(),
+            payload_binary: None,
+            funds: vec![],
+        },
+        message_id,
+        smart_contract_id: Addr::unchecked("contract0000"),
+        deadline: deadline(),
+        relayer_reward: None,
+    };
+    let mut accepted = vec![];
+    for signers in 1..=keys.len() {
+        let message_id = Uint256::from(2 * signers as u64);
+        let payloads = [
+            ("pause", pause(message_id)),
+            ("logic_call", logic_call(message_id + Uint256::one())),
+        ];
+        for (name, payload) in payloads {
+            let signing = &keys[keys.len() - signers..];
+            let mut builder = ConsensusBuilder::new(&valset, &payload, &thresholds)?;
+            for key in signing {
+                builder.sign(key)?;
+            }
+            if builder.has_consensus() {
+                let msg = match signers % 2 {
+                    0 => builder.build()?,
+                    _ => builder.build_compact()?,
+                };
+                relay(&mut deps, msg)?;
+                accepted.push((signers, name));
+                continue;
+            }
+            let power = builder.power();
+            assert!(builder.build().is_err());
+            let msg = sign_payload(&valset, &unchecked, signing, &payload)?;
+            assert_eq!(
+                relay(&mut deps, msg).unwrap_err(),
+                ContractError::InsufficientPower {
+                    power,
+                    threshold: thresholds.for_payload(&payload),
+                }
+            );
+        }
+    }
+    assert_eq!(
+        accepted,
+        [
+            (5, "logic_call"),
+            (6, "logic_call"),
+            (7, "pause"),
+            (7, "logic_call"),
+            (8, "pause"),
+            (8, "logic_call"),
+        ]
+    );
+    Ok(())
+}
@@ -0,0 +1,28 @@
+#!/bin/bash
+# Builds the contracts, or only the given packages, for deployment into artifacts/.
+#
+# std is rebuilt for the Wasm MVP, which needs a nightly toolchain with rust-src: the std that
+# ships with current Rust uses bulk memory instructions, which CosmWasm rejects.
+set -euo pipefail
+
+cd "$(dirname "$0")/.."
+
+packages=("$@")
+if [ ${#packages[@]} -eq 0 ]; then
+    for manifest in $(git ls-files '*/Cargo.toml' | xargs grep -l cdylib); do
+        packages+=("$(sed -n '0,/^name = /s/^name = "\(.*\)"/\1/p' "$manifest")")
+    done
+fi
+
+target=target/release-wasm
+# Replaces the rustflags of .cargo/config. The imports cosmwasm-std declares are provided by the
+# VM, so they are left undefined.
+CARGO_TARGET_WASM32_UNKNOWN_UNKNOWN_RUSTFLAGS="-C link-arg=-s -C link-arg=--allow-undefined -C target-cpu=mvp" \
+    cargo +nightly build --release --lib --target wasm32-unknown-unknown \
+    -Zbuild-std=std,panic_abort --target-dir "$target" "${packages[@]/#/-p}"
+
+mkdir -p artifacts
+for package in "${packages[@]}"; do
+    cp "$target/wasm32-unknown-unknown/release/${package//-/_}.wasm" artifacts/
+done
+(cd artifacts && sha256sum -- *.wasm > checksums.txt)